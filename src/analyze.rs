@@ -0,0 +1,651 @@
+use crate::chunk::Chunk;
+use crate::image::{ColourType, Image};
+use crate::png::Png;
+
+/// Chunk types defined by the PNG specification and its registered extensions.
+const KNOWN_CHUNK_TYPES: [&str; 31] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "tRNS", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV",
+    "cLLI", "tEXt", "zTXt", "iTXt", "bKGD", "hIST", "pHYs", "sPLT", "eXIf", "tIME", "acTL", "fcTL",
+    "fdAT", "oFFs", "pCAL", "sCAL", "sTER", "gIFg", "gIFx",
+];
+
+/// Ancillary chunks without a fixed maximum length are considered oversized above this length.
+const LARGE_CHUNK_LENGTH: usize = 64 * 1024;
+
+/// Chunk data shorter than this is too short for a meaningful entropy measurement.
+const MIN_ENTROPY_LENGTH: usize = 32;
+
+/// Chunk data is considered high-entropy above this fraction of the maximum possible entropy.
+const HIGH_ENTROPY_RATIO: f64 = 0.95;
+
+/// Images with fewer pixels are too small for the pixel tests to be meaningful.
+const MIN_PIXELS: usize = 256;
+
+/// Estimated embedding rates up to this value are within the error of the estimators on cover
+/// images.
+const EMBEDDING_RATE_NOISE: f64 = 0.05;
+
+/// A sign of hidden data, or the result of a test for it.
+#[derive(Debug)]
+pub(crate) struct Finding {
+    pub(crate) description: String,
+
+    /// How strongly the finding suggests hidden data, from 0 (not at all) to 1 (certainly).
+    pub(crate) weight: f64,
+}
+
+impl Finding {
+    fn new(weight: f64, description: String) -> Self {
+        Self {
+            description,
+            weight,
+        }
+    }
+}
+
+/// The result of analysing a PNG file for hidden data.
+#[derive(Debug)]
+pub(crate) struct Analysis {
+    pub(crate) findings: Vec<Finding>,
+}
+
+impl Analysis {
+    /// Returns a suspicion score from 0 to 100, combining the weights of all findings as if they
+    /// were independent probabilities of the file containing hidden data.
+    pub(crate) fn score(&self) -> u32 {
+        let innocent: f64 = self
+            .findings
+            .iter()
+            .map(|finding| 1.0 - finding.weight.clamp(0.0, 1.0))
+            .product();
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let score = ((1.0 - innocent) * 100.0).round() as u32;
+        score
+    }
+}
+
+/// Analyses the chunks and the image data of the PNG for signs of hidden data.
+pub(crate) fn analyze(png: &Png) -> Analysis {
+    let mut findings = chunk_findings(png);
+
+    match Image::decode(png) {
+        Ok(image) => findings.extend(pixel_findings(&image)),
+        Err(error) => findings.push(Finding::new(
+            0.3,
+            format!("image data can't be decoded: {error}"),
+        )),
+    }
+
+    Analysis { findings }
+}
+
+/// Looks for private, unknown, oversized and high-entropy chunks and data after `IEND`.
+fn chunk_findings(png: &Png) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        let length = chunk.data().len();
+
+        if !chunk_type.is_public() {
+            findings.push(Finding::new(
+                0.5,
+                format!("private chunk {name} ({length} bytes)"),
+            ));
+        } else if !KNOWN_CHUNK_TYPES.contains(&name.as_str()) {
+            findings.push(Finding::new(
+                0.3,
+                format!("unknown chunk {name} ({length} bytes)"),
+            ));
+        } else if !chunk_type.is_critical() {
+            match max_length(&name) {
+                Some(max_length) if length > max_length => findings.push(Finding::new(
+                    0.4,
+                    format!(
+                        "oversized {name} chunk ({length} bytes, expected at most {max_length})"
+                    ),
+                )),
+                None if length > LARGE_CHUNK_LENGTH => findings.push(Finding::new(
+                    0.2,
+                    format!("large {name} chunk ({length} bytes)"),
+                )),
+                _ => {}
+            }
+        }
+
+        if length >= MIN_ENTROPY_LENGTH && !is_compressed(chunk) {
+            let entropy = entropy(chunk.data());
+            if entropy > HIGH_ENTROPY_RATIO * max_entropy(length) {
+                findings.push(Finding::new(
+                    0.5,
+                    format!("high-entropy {name} chunk data ({entropy:.2} bits per byte)"),
+                ));
+            }
+        }
+    }
+
+    let trailing_length = png.trailing_data().len();
+    if trailing_length > 0 {
+        findings.push(Finding::new(
+            0.8,
+            format!("{trailing_length} bytes of data after IEND"),
+        ));
+    }
+
+    findings
+}
+
+/// Returns the maximum length of the data of ancillary chunks of the given type with a bounded
+/// length.
+fn max_length(chunk_type: &str) -> Option<usize> {
+    match chunk_type {
+        "sRGB" | "sTER" => Some(1),
+        "gAMA" | "sBIT" | "cICP" | "gIFg" => Some(4),
+        "bKGD" => Some(6),
+        "tIME" => Some(7),
+        "cLLI" | "acTL" => Some(8),
+        "pHYs" | "oFFs" => Some(9),
+        "mDCV" => Some(24),
+        "fcTL" => Some(26),
+        "cHRM" => Some(32),
+        "tRNS" => Some(256),
+        "hIST" => Some(512),
+        _ => None,
+    }
+}
+
+/// Returns `true` if the chunk data is compressed, and so is expected to have a high entropy.
+fn is_compressed(chunk: &Chunk) -> bool {
+    match chunk.chunk_type().to_string().as_str() {
+        "IDAT" | "fdAT" | "zTXt" | "iCCP" => true,
+        // The compression flag follows the null-terminated keyword
+        "iTXt" => chunk
+            .data()
+            .iter()
+            .position(|&byte| byte == 0)
+            .and_then(|position| chunk.data().get(position + 1))
+            .is_some_and(|&flag| flag == 1),
+        _ => false,
+    }
+}
+
+/// Returns the Shannon entropy of the data in bits per byte, from 0 to 8.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0_usize; 256];
+    for &byte in data {
+        counts[usize::from(byte)] += 1;
+    }
+
+    let length = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let probability = count as f64 / length;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+/// Returns the highest entropy data of the given length can have, in bits per byte.
+#[allow(clippy::cast_precision_loss)]
+fn max_entropy(length: usize) -> f64 {
+    (length.min(256) as f64).log2()
+}
+
+/// Runs the chi-square attack, RS analysis and sample pair analysis on the colour samples of the
+/// image. The tests are designed to detect LSB replacement, and underestimate LSB matching.
+fn pixel_findings(image: &Image) -> Vec<Finding> {
+    let Some(planes) = planes(image) else {
+        return vec![Finding::new(
+            0.0,
+            "pixel tests skipped: only greyscale and RGB images with a bit depth of 8 or 16 are \
+             supported"
+                .to_string(),
+        )];
+    };
+    if image.width() * image.height() < MIN_PIXELS {
+        return vec![Finding::new(
+            0.0,
+            "pixel tests skipped: the image is too small".to_string(),
+        )];
+    }
+
+    let rate_weight = |rate: f64| ((rate - EMBEDDING_RATE_NOISE) / 0.3).clamp(0.0, 0.8);
+    let mut findings = Vec::new();
+
+    if let Some(probability) = chi_square_attack(&planes) {
+        findings.push(Finding::new(
+            if probability >= 0.95 { 0.6 } else { 0.0 },
+            format!("chi-square attack: {probability:.2} probability of LSB embedding"),
+        ));
+    }
+    if let Some(rate) = rs_analysis(&planes) {
+        findings.push(Finding::new(
+            rate_weight(rate),
+            format!("RS analysis: estimated embedding rate {rate:.2}"),
+        ));
+    }
+    if let Some(rate) = sample_pair_analysis(&planes) {
+        findings.push(Finding::new(
+            rate_weight(rate),
+            format!("sample pair analysis: estimated embedding rate {rate:.2}"),
+        ));
+    }
+
+    findings
+}
+
+/// The least significant byte of each sample of a colour channel of an image, in row-major order.
+struct Plane {
+    width: usize,
+    values: Vec<u8>,
+}
+
+impl Plane {
+    fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.values.chunks_exact(self.width)
+    }
+}
+
+/// Splits the colour channels of the image into planes, leaving out alpha. Returns `None` for
+/// indexed-colour images and bit depths below 8, where the tests don't apply.
+fn planes(image: &Image) -> Option<Vec<Plane>> {
+    let header = image.header();
+    let colour_channels = match header.colour_type {
+        ColourType::Greyscale | ColourType::GreyscaleAlpha => 1,
+        ColourType::Rgb | ColourType::Rgba => 3,
+        ColourType::Indexed => return None,
+    };
+    if header.bit_depth < 8 || image.width() == 0 {
+        return None;
+    }
+
+    let channels = image.channels();
+    Some(
+        (0..colour_channels)
+            .map(|channel| Plane {
+                width: image.width(),
+                values: image
+                    .samples()
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .map(|&sample| sample.to_be_bytes()[1])
+                    .collect(),
+            })
+            .collect(),
+    )
+}
+
+/// Runs the chi-square attack of Westfeld and Pfitzmann. LSB replacement with random bits evens
+/// out the frequencies of each pair of values `2k` and `2k + 1`. Returns the probability that the
+/// observed frequencies come from such evened-out pairs.
+#[allow(clippy::cast_precision_loss)]
+fn chi_square_attack(planes: &[Plane]) -> Option<f64> {
+    let mut histogram = [0_usize; 256];
+    for &value in planes.iter().flat_map(|plane| &plane.values) {
+        histogram[usize::from(value)] += 1;
+    }
+
+    let (chi_square, categories) = histogram
+        .chunks_exact(2)
+        .filter(|pair| pair[0] + pair[1] > 0)
+        .fold((0.0, 0_u32), |(chi_square, categories), pair| {
+            let expected = (pair[0] + pair[1]) as f64 / 2.0;
+            let difference = pair[0] as f64 - expected;
+            (
+                chi_square + difference * difference / expected,
+                categories + 1,
+            )
+        });
+    if categories < 2 {
+        return None;
+    }
+
+    let degrees_of_freedom = f64::from(categories - 1);
+    Some(upper_regularized_gamma(
+        degrees_of_freedom / 2.0,
+        chi_square / 2.0,
+    ))
+}
+
+/// Runs the RS analysis of Fridrich, Goljan and Du, estimating the fraction of samples whose
+/// least significant bit was replaced.
+///
+/// Groups of four adjacent samples are classified as regular or singular depending on whether
+/// flipping the LSBs of the middle samples increases or decreases their noisiness. In cover
+/// images the proportions for positive and negative flipping are about equal, while LSB
+/// replacement drives them apart.
+#[allow(clippy::cast_precision_loss)]
+fn rs_analysis(planes: &[Plane]) -> Option<f64> {
+    const MASK: [i32; 4] = [0, 1, 1, 0];
+
+    let groups: Vec<[i32; 4]> = planes
+        .iter()
+        .flat_map(Plane::rows)
+        .flat_map(|row| row.chunks_exact(4))
+        .map(|group| [0, 1, 2, 3].map(|i| i32::from(group[i])))
+        .collect();
+    if groups.is_empty() {
+        return None;
+    }
+
+    let noisiness =
+        |group: &[i32; 4]| -> i32 { group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum() };
+    let flip = |value: i32, direction: i32| match direction {
+        1 => value ^ 1,
+        -1 => ((value + 1) ^ 1) - 1,
+        _ => value,
+    };
+    // Returns the difference between the proportions of regular and singular groups
+    let regular_minus_singular = |all_flipped: bool, sign: i32| -> f64 {
+        let balance: i64 = groups
+            .iter()
+            .map(|group| {
+                let group = group.map(|value| if all_flipped { value ^ 1 } else { value });
+                let mut flipped = group;
+                for (value, mask) in flipped.iter_mut().zip(MASK) {
+                    *value = flip(*value, sign * mask);
+                }
+                i64::from(noisiness(&flipped).cmp(&noisiness(&group)) as i8)
+            })
+            .sum();
+        balance as f64 / groups.len() as f64
+    };
+
+    let d0 = regular_minus_singular(false, 1);
+    let d1 = regular_minus_singular(true, 1);
+    let negative_d0 = regular_minus_singular(false, -1);
+    let negative_d1 = regular_minus_singular(true, -1);
+
+    let root = smallest_root(
+        2.0 * (d1 + d0),
+        negative_d0 - negative_d1 - d1 - 3.0 * d0,
+        d0 - negative_d0,
+    )?;
+    Some((root / (root - 0.5)).clamp(0.0, 1.0))
+}
+
+/// Runs the sample pair analysis of Dumitrescu, Wu and Wang, estimating the fraction of samples
+/// whose least significant bit was replaced from the statistics of horizontally adjacent samples.
+#[allow(clippy::cast_precision_loss)]
+fn sample_pair_analysis(planes: &[Plane]) -> Option<f64> {
+    let (mut x, mut y, mut close, mut pairs) = (0_usize, 0_usize, 0_usize, 0_usize);
+    for pair in planes
+        .iter()
+        .flat_map(Plane::rows)
+        .flat_map(|row| row.windows(2))
+    {
+        let (u, v) = (pair[0], pair[1]);
+        pairs += 1;
+        if (v % 2 == 0 && u < v) || (v % 2 == 1 && u > v) {
+            x += 1;
+        }
+        if (v % 2 == 0 && u > v) || (v % 2 == 1 && u < v) {
+            y += 1;
+        }
+        if u >> 1 == v >> 1 {
+            close += 1;
+        }
+    }
+    if close == 0 {
+        return None;
+    }
+
+    let (x, y, close, pairs) = (x as f64, y as f64, close as f64, pairs as f64);
+    let root = smallest_root(close / 2.0, 2.0 * x - pairs, y - x)?;
+    Some(root.clamp(0.0, 1.0))
+}
+
+/// Returns the root of `a * x^2 + b * x + c = 0` with the smallest absolute value.
+fn smallest_root(a: f64, b: f64, c: f64) -> Option<f64> {
+    if a.abs() < f64::EPSILON {
+        return (b.abs() >= f64::EPSILON).then(|| -c / b);
+    }
+
+    // Estimates close to 1 can make the discriminant slightly negative, take the vertex then
+    let discriminant = (b * b - 4.0 * a * c).max(0.0);
+    let roots = [1.0, -1.0].map(|sign| (-b + sign * discriminant.sqrt()) / (2.0 * a));
+    Some(if roots[0].abs() < roots[1].abs() {
+        roots[0]
+    } else {
+        roots[1]
+    })
+}
+
+/// Returns the regularised upper incomplete gamma function Q(a, x), the probability that a
+/// chi-square distributed variable with `2a` degrees of freedom exceeds `2x`.
+#[allow(clippy::many_single_char_names)]
+fn upper_regularized_gamma(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+    const MAX_ITERATIONS: u32 = 1000;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        // Series expansion of the lower incomplete gamma function
+        let (mut term, mut sum, mut denominator) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..MAX_ITERATIONS {
+            denominator += 1.0;
+            term *= x / denominator;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        (1.0 - sum * prefactor).clamp(0.0, 1.0)
+    } else {
+        // Continued fraction, evaluated with the modified Lentz method
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for i in 1..MAX_ITERATIONS {
+            let i = f64::from(i);
+            let an = -i * (i - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            fraction *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (fraction * prefactor).clamp(0.0, 1.0)
+    }
+}
+
+/// Returns the natural logarithm of the gamma function for `x >= 0.5`, using the Lanczos
+/// approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    let x = x - 1.0;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .zip(1..)
+        .fold(COEFFICIENTS[0], |sum, (coefficient, i)| {
+            sum + coefficient / (x + f64::from(i))
+        });
+    let t = x + 7.5;
+
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::image::Header;
+    use rand::{Rng, SeedableRng};
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    /// A smooth greyscale image with a little noise, resembling a photograph.
+    fn cover_image() -> Image {
+        let (width, height) = (128, 128);
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+        let samples = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let smooth =
+                    128.0 + 60.0 * (f64::from(x) / 9.0).sin() + 40.0 * (f64::from(y) / 13.0).cos();
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let sample = (smooth + rng.gen_range(-3.0..3.0)).round() as u16;
+                sample
+            })
+            .collect();
+
+        Image::new(
+            Header {
+                width,
+                height,
+                bit_depth: 8,
+                colour_type: ColourType::Greyscale,
+                interlaced: false,
+            },
+            samples,
+        )
+    }
+
+    /// Replaces the least significant bit of the given fraction of samples with a random bit.
+    fn replace_lsbs(image: &mut Image, rate: f64) {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(2);
+        for sample in image.samples_mut() {
+            if rng.gen_bool(rate) {
+                *sample = (*sample & !1) | u16::from(rng.gen::<bool>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert!(entropy(&[7; 100]).abs() < 1e-9);
+        assert!((entropy(&(0..=255).collect::<Vec<u8>>()) - 8.0).abs() < 1e-9);
+        assert!((entropy(b"abab") - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_upper_regularized_gamma() {
+        // With 2 degrees of freedom, Q(1, x) = e^-x
+        for x in [0.1, 1.0, 5.0, 20.0] {
+            assert!((upper_regularized_gamma(1.0, x) - (-x).exp()).abs() < 1e-9);
+        }
+        assert!((ln_gamma(5.0) - 24.0_f64.ln()).abs() < 1e-9);
+        assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_chunk_findings() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for chunk in [
+            chunk("IHDR", &[0; 13]),
+            chunk("ruSt", b"hidden"),
+            chunk("aBCd", b""),
+            chunk("tIME", &[0; 20]),
+            chunk("tEXt", &(0..=255).collect::<Vec<u8>>()),
+            chunk("IEND", &[]),
+        ] {
+            bytes.extend(chunk.as_bytes());
+        }
+        bytes.extend(b"trailing");
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+
+        let descriptions: Vec<String> = chunk_findings(&png)
+            .into_iter()
+            .map(|finding| finding.description)
+            .collect();
+        assert_eq!(
+            descriptions,
+            [
+                "private chunk ruSt (6 bytes)",
+                "unknown chunk aBCd (0 bytes)",
+                "oversized tIME chunk (20 bytes, expected at most 7)",
+                "high-entropy tEXt chunk data (8.00 bits per byte)",
+                "8 bytes of data after IEND",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_score() {
+        let analysis = Analysis { findings: vec![] };
+        assert_eq!(analysis.score(), 0);
+
+        let analysis = Analysis {
+            findings: vec![
+                Finding::new(0.5, String::new()),
+                Finding::new(0.5, String::new()),
+            ],
+        };
+        assert_eq!(analysis.score(), 75);
+    }
+
+    #[test]
+    fn test_pixel_tests_on_cover_image() {
+        let planes = planes(&cover_image()).unwrap();
+
+        assert!(rs_analysis(&planes).unwrap() < 0.15);
+        assert!(sample_pair_analysis(&planes).unwrap() < 0.15);
+    }
+
+    #[test]
+    fn test_pixel_tests_on_half_embedded_image() {
+        let mut image = cover_image();
+        replace_lsbs(&mut image, 0.5);
+        let planes = planes(&image).unwrap();
+
+        assert!(chi_square_attack(&planes).unwrap() > 0.95);
+        assert!((0.35..0.75).contains(&rs_analysis(&planes).unwrap()));
+        assert!((0.35..0.75).contains(&sample_pair_analysis(&planes).unwrap()));
+    }
+
+    #[test]
+    fn test_pixel_tests_on_fully_embedded_image() {
+        let mut image = cover_image();
+        replace_lsbs(&mut image, 1.0);
+        let planes = planes(&image).unwrap();
+
+        assert!(rs_analysis(&planes).unwrap() > 0.75);
+        assert!(sample_pair_analysis(&planes).unwrap() > 0.75);
+    }
+
+    #[test]
+    fn test_chi_square_attack_on_uneven_pairs() {
+        let mut image = cover_image();
+        for sample in image.samples_mut() {
+            *sample &= !1;
+        }
+
+        assert!(chi_square_attack(&planes(&image).unwrap()).unwrap() < 0.05);
+    }
+}
@@ -1,12 +1,14 @@
 use std::path::PathBuf;
 
-use clap::{AppSettings, Parser, ValueHint};
+use clap::{AppSettings, ArgEnum, Parser, ValueHint};
 
 #[derive(Debug, Parser)]
 #[clap(
     author,
     version,
     setting = AppSettings::SubcommandRequiredElseHelp,
+    after_help = "Use '-' as a path to read a PNG file from standard input or write it to standard \
+                  output.",
 )]
 pub struct Opts {
     /// Prints verbose information
@@ -23,6 +25,34 @@ pub(crate) enum SubCommand {
     Decode(Decode),
     Remove(Remove),
     Print(Print),
+    Strip(Strip),
+    Scan(Scan),
+    Carve(Carve),
+    Extract(Extract),
+    Import(Import),
+    Diff(Diff),
+    ComparePixels(ComparePixels),
+    Textconv(Textconv),
+    ChunkType(ChunkType),
+    Reencode(Reencode),
+    Hide(Hide),
+    Reveal(Reveal),
+    Capacity(Capacity),
+    Analyze(Analyze),
+    Bitplane(Bitplane),
+    Create(Create),
+}
+
+/// Options for processing multiple PNG files at once.
+#[derive(Debug, Parser)]
+pub(crate) struct Batch {
+    /// Search directories for PNG files recursively.
+    #[clap(long, short = 'R')]
+    pub(crate) recursive: bool,
+
+    /// Number of files to process in parallel. Defaults to the number of available CPUs.
+    #[clap(long, short)]
+    pub(crate) jobs: Option<usize>,
 }
 
 /// Encode a message in a PNG file.
@@ -31,20 +61,81 @@ pub(crate) enum SubCommand {
     setting = AppSettings::ArgRequiredElseHelp,
 )]
 pub(crate) struct Encode {
-    /// Path to the PNG file to encode the message in.
-    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    /// Path to the PNG file to encode the message in. May also be a directory or a glob pattern.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
     pub(crate) in_file: PathBuf,
 
-    /// A 4-character long ASCII alphabetic string.
-    pub(crate) chunk_type: crate::chunk_type::ChunkType,
+    /// A 4-character long ASCII alphabetic string, `derive` to derive the chunk type from the
+    /// secret, or `zTXt` or `iTXt` to camouflage the message as standard metadata.
+    #[clap(parse(try_from_str = parse_message_chunk_type))]
+    pub(crate) chunk_type: MessageChunkType,
 
     /// Message to encode.
     pub(crate) message: String,
 
     /// Path to the PNG file to save the encoded image as. Optional. If this is not specified, the
-    /// input PNG file is updated in place.
-    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    /// input PNG file is updated in place. Must be a directory when processing multiple files.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
     pub(crate) out_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) secret: Secret,
+
+    /// Keyword of the `zTXt` chunk to camouflage the message as, `Comment` if not specified. Only
+    /// applies to `zTXt`.
+    #[clap(long)]
+    pub(crate) keyword: Option<String>,
+
+    /// Compress the message. Doesn't apply to camouflaged messages.
+    #[clap(long)]
+    pub(crate) compress: bool,
+
+    /// Encrypt the message with this passphrase. Doesn't apply to camouflaged messages.
+    #[clap(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    pub(crate) passphrase: Option<String>,
+
+    /// Store the message as is, without the envelope describing how it is stored, like older
+    /// versions of pngme did.
+    #[clap(long, conflicts_with = "compress")]
+    pub(crate) raw: bool,
+
+    /// Don't check that the pixels of the written file are the same as those of the input file.
+    #[clap(long)]
+    pub(crate) no_verify: bool,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// The chunk type of a message.
+#[derive(Clone, Debug)]
+pub(crate) enum MessageChunkType {
+    Given(crate::chunk_type::ChunkType),
+    Derived,
+    Camouflaged(Camouflage),
+}
+
+/// Standard metadata to camouflage a message as.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Camouflage {
+    /// Compressed text in a `zTXt` chunk with a common keyword, such as `Comment`.
+    CompressedText,
+
+    /// The description field of an XMP packet in an `iTXt` chunk.
+    Xmp,
+}
+
+/// Options for deriving the chunk type of a message from a shared secret.
+#[derive(Debug, Parser)]
+pub(crate) struct Secret {
+    /// Secret to derive the chunk type from, so that only those who know it can tell which chunk
+    /// contains the message.
+    #[clap(long, env = "PNGME_SECRET", hide_env_values = true)]
+    pub(crate) secret: Option<String>,
+
+    /// Label to derive the chunk type with, to store several messages using the same secret.
+    #[clap(long, default_value = "message")]
+    pub(crate) label: String,
 }
 
 /// Decode a message in a PNG file.
@@ -53,35 +144,711 @@ pub(crate) struct Encode {
     setting = AppSettings::ArgRequiredElseHelp,
 )]
 pub(crate) struct Decode {
-    /// Path to the PNG file to decode the message from.
-    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    /// Path to the PNG file to decode the message from. May also be a directory or a glob
+    /// pattern.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
     pub(crate) in_file: PathBuf,
 
-    /// A 4-character long ASCII alphabetic string.
-    pub(crate) chunk_type: crate::chunk_type::ChunkType,
+    /// A 4-character long ASCII alphabetic string, or `zTXt` or `iTXt` if the message was
+    /// camouflaged as standard metadata. Optional if a secret is specified, in which case the
+    /// chunk type is derived from the secret.
+    #[clap(
+        required_unless_present = "secret",
+        parse(try_from_str = parse_message_chunk_type)
+    )]
+    pub(crate) chunk_type: Option<MessageChunkType>,
+
+    #[clap(flatten)]
+    pub(crate) secret: Secret,
+
+    /// Keyword of the `zTXt` chunk the message was camouflaged as, `Comment` if not specified.
+    /// Only applies to `zTXt`.
+    #[clap(long)]
+    pub(crate) keyword: Option<String>,
+
+    /// Passphrase to decrypt the message with, if it is encrypted.
+    #[clap(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    pub(crate) passphrase: Option<String>,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
 }
 
-/// Remove a message from a PNG file.
+/// Remove messages from a PNG file.
 #[derive(Debug, Parser)]
 #[clap(
     setting = AppSettings::ArgRequiredElseHelp,
 )]
 pub(crate) struct Remove {
-    /// Path to the PNG file to remove the message from.
+    /// Path to the PNG file to remove the messages from. May also be a directory or a glob
+    /// pattern.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
+    pub(crate) in_file: PathBuf,
+
+    /// One or more 4-character long ASCII alphabetic strings.
+    #[clap(
+        required_unless_present = "all-private",
+        parse(try_from_str = parse_modifiable_chunk_type)
+    )]
+    pub(crate) chunk_types: Vec<crate::chunk_type::ChunkType>,
+
+    /// Remove all chunks which could possibly contain messages.
+    #[clap(long, conflicts_with = "chunk-types")]
+    pub(crate) all_private: bool,
+
+    /// Path to the PNG file to save the resulting image as. Optional. If this is not specified,
+    /// the input PNG file is updated in place. Must be a directory when processing multiple files.
+    #[clap(long, short, parse(from_os_str), value_hint = ValueHint::AnyPath)]
+    pub(crate) out_file: Option<PathBuf>,
+
+    /// Don't check that the pixels of the written file are the same as those of the input file.
+    #[clap(long)]
+    pub(crate) no_verify: bool,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Print a list of PNG chunks that can be searched for messages
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Print {
+    /// Paths to the PNG files to list all chunks of. May also be directories or glob patterns.
+    #[clap(
+        required = true,
+        min_values = 1,
+        parse(from_os_str),
+        value_hint = ValueHint::AnyPath
+    )]
+    pub(crate) in_files: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Strip metadata which could leak information from a PNG file.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Strip {
+    /// Paths to the PNG files to strip metadata from. May also be directories or glob patterns.
+    #[clap(
+        required = true,
+        min_values = 1,
+        parse(from_os_str),
+        value_hint = ValueHint::AnyPath
+    )]
+    pub(crate) in_files: Vec<PathBuf>,
+
+    /// Path to the PNG file to save the stripped image as. Optional. If this is not specified, the
+    /// input PNG file is updated in place. Must be a directory when processing multiple files.
+    #[clap(long, short, parse(from_os_str), value_hint = ValueHint::AnyPath)]
+    pub(crate) out_file: Option<PathBuf>,
+
+    /// Which chunks to remove.
+    #[clap(long, short, arg_enum, default_value = "keep-only-critical")]
+    pub(crate) preset: StripPreset,
+
+    /// Chunk types to keep regardless of the preset.
+    #[clap(long, short, value_name = "CHUNK_TYPE", multiple_occurrences(true))]
+    pub(crate) keep: Vec<crate::chunk_type::ChunkType>,
+
+    /// Chunk types to remove regardless of the preset.
+    #[clap(long, short, value_name = "CHUNK_TYPE", multiple_occurrences(true))]
+    pub(crate) remove: Vec<crate::chunk_type::ChunkType>,
+
+    /// Keep any data found after the IEND chunk.
+    #[clap(long)]
+    pub(crate) keep_trailing_data: bool,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Sets of chunks removed by the `strip` subcommand.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum StripPreset {
+    /// Remove every ancillary chunk.
+    KeepOnlyCritical,
+
+    /// Remove every ancillary chunk except those affecting how colours are displayed.
+    KeepColourManagement,
+
+    /// Remove private chunks only.
+    RemovePrivateOnly,
+}
+
+/// Search PNG files for chunks which could contain messages.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Scan {
+    /// Paths to the PNG files to search. May also be directories or glob patterns.
+    #[clap(
+        required = true,
+        min_values = 1,
+        parse(from_os_str),
+        value_hint = ValueHint::AnyPath
+    )]
+    pub(crate) in_files: Vec<PathBuf>,
+
+    /// List chunks whose type matches this regular expression instead of chunks which could
+    /// possibly contain messages.
+    #[clap(long = "type", short, value_name = "PATTERN")]
+    pub(crate) type_pattern: Option<regex::Regex>,
+
+    /// List only chunks whose text matches this regular expression. Textual chunks and messages
+    /// are decoded and decompressed first.
+    #[clap(long, short = 'e', value_name = "PATTERN")]
+    pub(crate) regex: Option<regex::Regex>,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Recover PNG files embedded in arbitrary binary data, such as disk images or memory dumps.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Carve {
+    /// Path to the file to search for embedded PNG files.
     #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
     pub(crate) in_file: PathBuf,
 
+    /// Path to the directory to save the recovered PNG files in. It is created if it doesn't
+    /// exist.
+    #[clap(
+        long,
+        short,
+        default_value = ".",
+        parse(from_os_str),
+        value_hint = ValueHint::DirPath
+    )]
+    pub(crate) out_dir: PathBuf,
+}
+
+/// Extract all messages from a PNG file to a directory.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Extract {
+    /// Path to the PNG file to extract the messages from.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) in_file: PathBuf,
+
+    /// Path to the directory to save the messages and the manifest in. It is created if it
+    /// doesn't exist.
+    #[clap(long, short, parse(from_os_str), value_hint = ValueHint::DirPath)]
+    pub(crate) out: PathBuf,
+
+    /// Format of the manifest describing the extracted messages.
+    #[clap(long, short, arg_enum, default_value = "json")]
+    pub(crate) format: ManifestFormat,
+
+    /// Passphrase to decrypt encrypted messages with.
+    #[clap(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    pub(crate) passphrase: Option<String>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ManifestFormat {
+    Json,
+    Toml,
+}
+
+/// Add messages described by a manifest file to a PNG file.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Import {
+    /// Path to the PNG file to add the messages to.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) in_file: PathBuf,
+
+    /// Path to the PNG file to save the resulting image as. Optional. If this is not specified,
+    /// the input PNG file is updated in place.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) out_file: Option<PathBuf>,
+
+    /// Path to the TOML manifest describing the messages to add.
+    #[clap(long, short, parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) manifest: PathBuf,
+
+    /// Passphrase to encrypt messages with.
+    #[clap(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    pub(crate) passphrase: Option<String>,
+}
+
+/// Compare the chunks of two PNG files.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Diff {
+    /// Path to the first PNG file.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) a: PathBuf,
+
+    /// Path to the second PNG file.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) b: PathBuf,
+}
+
+/// Compare the pixels of two PNG files, ignoring how they are stored. Exits with an error if
+/// they differ.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct ComparePixels {
+    /// Path to the first PNG file.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) a: PathBuf,
+
+    /// Path to the second PNG file.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) b: PathBuf,
+}
+
+/// Print a text description of a PNG file, for use as a git textconv filter.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Textconv {
+    /// Path to the PNG file to describe.
+    #[clap(
+        required_unless_present = "install",
+        parse(from_os_str),
+        value_hint = ValueHint::FilePath
+    )]
+    pub(crate) in_file: Option<PathBuf>,
+
+    /// Configure git to use this command for diffing files marked with `diff=pngme` in
+    /// `.gitattributes`, instead of describing a PNG file.
+    #[clap(long, conflicts_with = "in-file")]
+    pub(crate) install: bool,
+
+    /// Update the global git configuration instead of the configuration of the current repository.
+    #[clap(long, requires = "install")]
+    pub(crate) global: bool,
+}
+
+/// Suggest and explain chunk types which can be used for messages.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::SubcommandRequiredElseHelp,
+)]
+pub(crate) struct ChunkType {
+    #[clap(subcommand)]
+    pub(crate) subcommand: ChunkTypeCommand,
+}
+
+#[derive(Debug, Parser)]
+pub(crate) enum ChunkTypeCommand {
+    Suggest(SuggestChunkType),
+    Explain(ExplainChunkType),
+}
+
+/// Suggest chunk types which can be used for messages.
+#[derive(Debug, Parser)]
+pub(crate) struct SuggestChunkType {
+    /// A word to derive the chunk type from, by fixing the case of its first four letters.
+    /// Optional. If this is not specified, random chunk types are suggested.
+    pub(crate) seed: Option<String>,
+
+    /// Number of random chunk types to suggest.
+    #[clap(long, short = 'n', default_value_t = 1, conflicts_with = "seed")]
+    pub(crate) count: usize,
+}
+
+/// Explain the properties of a chunk type and whether it can be used for messages.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct ExplainChunkType {
     /// A 4-character long ASCII alphabetic string.
     pub(crate) chunk_type: crate::chunk_type::ChunkType,
 }
 
-/// Print a list of PNG chunks that can be searched for messages
+/// Parses the chunk type of a message, which may be `derive` to derive it from the secret, or
+/// `zTXt` or `iTXt` to camouflage the message as standard metadata.
+fn parse_message_chunk_type(s: &str) -> Result<MessageChunkType, String> {
+    match s {
+        "derive" => Ok(MessageChunkType::Derived),
+        "zTXt" => Ok(MessageChunkType::Camouflaged(Camouflage::CompressedText)),
+        "iTXt" => Ok(MessageChunkType::Camouflaged(Camouflage::Xmp)),
+        _ => parse_modifiable_chunk_type(s).map(MessageChunkType::Given),
+    }
+}
+
+/// Parses a chunk type which can be used for messages, suggesting a corrected chunk type if it
+/// can't be used.
+fn parse_modifiable_chunk_type(s: &str) -> Result<crate::chunk_type::ChunkType, String> {
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    match ChunkType::from_str(s) {
+        Ok(chunk_type) if chunk_type.is_modifiable() => Ok(chunk_type),
+        Ok(chunk_type) => Err(format!(
+            "chunk type can't be used for messages, try '{}' instead (run 'pngme chunk-type \
+            explain {chunk_type}' for details)",
+            chunk_type.to_modifiable()
+        )),
+        Err(_) => Err(match ChunkType::from_seed(s) {
+            Some(suggestion) => format!(
+                "expected a 4-character long ASCII alphabetic string, try '{suggestion}' instead"
+            ),
+            None => "expected a 4-character long ASCII alphabetic string".to_string(),
+        }),
+    }
+}
+
+/// Re-encode the image data of a PNG file, keeping all other chunks in place.
 #[derive(Debug, Parser)]
 #[clap(
     setting = AppSettings::ArgRequiredElseHelp,
 )]
-pub(crate) struct Print {
-    /// Path to the PNG file to list all chunks.
+pub(crate) struct Reencode {
+    /// Path to the PNG file to re-encode. May also be a directory or a glob pattern.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
+    pub(crate) in_file: PathBuf,
+
+    /// Path to the PNG file to save the re-encoded image as. Optional. If this is not specified,
+    /// the input PNG file is updated in place. Must be a directory when processing multiple files.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
+    pub(crate) out_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) encoding: Encoding,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Options for encoding image data.
+#[derive(Debug, Parser)]
+pub(crate) struct Encoding {
+    /// Filter to apply to each scanline of the image.
+    #[clap(long, arg_enum, default_value = "adaptive")]
+    pub(crate) filter: Filter,
+
+    /// zlib compression level, from 0 (no compression) to 9 (best compression).
+    #[clap(
+        long,
+        default_value_t = 9,
+        value_parser = clap::value_parser!(u32).range(..=9)
+    )]
+    pub(crate) level: u32,
+
+    /// Maximum number of bytes of image data in each IDAT chunk.
+    #[clap(
+        long,
+        value_name = "BYTES",
+        default_value_t = 8192,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub(crate) idat_size: u32,
+}
+
+/// Filters applied to the scanlines of an image before compressing it.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Filter {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+
+    /// Choose the filter which is likely to compress best for each scanline.
+    Adaptive,
+}
+
+/// Hide a message in the pixels of a PNG file.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Hide {
+    /// Path to the PNG file to hide the message in. May also be a directory or a glob pattern.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
+    pub(crate) in_file: PathBuf,
+
+    /// Message to hide.
+    pub(crate) message: String,
+
+    /// Path to the PNG file to save the resulting image as. Optional. If this is not specified,
+    /// the input PNG file is updated in place. Must be a directory when processing multiple files.
+    #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
+    pub(crate) out_file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) hiding: Hiding,
+
+    #[clap(flatten)]
+    pub(crate) encoding: Encoding,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Reveal a message hidden in the pixels of a PNG file.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Reveal {
+    /// Paths to the PNG files to reveal the message from. May also be directories or glob
+    /// patterns.
+    #[clap(
+        required = true,
+        min_values = 1,
+        parse(from_os_str),
+        value_hint = ValueHint::AnyPath
+    )]
+    pub(crate) in_files: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) hiding: Hiding,
+
+    /// Print the data stored in an image created with 'pngme create --data-image' instead, as raw
+    /// bytes. No passphrase is needed.
+    #[clap(long)]
+    pub(crate) data_image: bool,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Options for hiding messages in the pixels of PNG files. Revealing a message requires the same
+/// options as hiding it.
+#[derive(Debug, Parser)]
+pub(crate) struct Hiding {
+    /// Method used to hide the message.
+    #[clap(long, short, arg_enum, default_value = "lsb")]
+    pub(crate) method: HidingMethod,
+
+    /// Passphrase to key the hidden message with. The message can't be revealed without it.
+    #[clap(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    pub(crate) passphrase: Option<String>,
+
+    #[clap(flatten)]
+    pub(crate) selection: PixelSelection,
+}
+
+/// Ways of hiding messages in the pixels of PNG files.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum HidingMethod {
+    /// Scatter the bits of the message across the least significant bits of the samples.
+    Lsb,
+
+    /// Encode the message in the order of the palette entries of indexed-colour images, leaving
+    /// the rendered image unchanged.
+    Palette,
+
+    /// Encode the message in the filter types of the scanlines, leaving the pixels unchanged.
+    /// --filter doesn't apply.
+    Filter,
+
+    /// Encode the message in the lengths of the IDAT chunks, leaving the image data unchanged.
+    /// Only --idat-size of the encoding options applies.
+    Idat,
+}
+
+/// Options for choosing the pixels which carry hidden messages with the lsb method.
+#[derive(Debug, Parser)]
+pub(crate) struct PixelSelection {
+    /// Don't use pixels in flat regions of the image, where changes are easier to spot.
+    #[clap(long)]
+    pub(crate) skip_flat: bool,
+
+    /// Don't use fully transparent pixels.
+    #[clap(long)]
+    pub(crate) skip_transparent: bool,
+}
+
+/// Print how many bytes can be hidden in the pixels of a PNG file.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Capacity {
+    /// Paths to the PNG files. May also be directories or glob patterns.
+    #[clap(
+        required = true,
+        min_values = 1,
+        parse(from_os_str),
+        value_hint = ValueHint::AnyPath
+    )]
+    pub(crate) in_files: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) selection: PixelSelection,
+
+    /// Size of the IDAT chunks to assume for the idat method.
+    #[clap(
+        long,
+        value_name = "BYTES",
+        default_value_t = 8192,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub(crate) idat_size: u32,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Analyse PNG files for signs of hidden data.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Analyze {
+    /// Paths to the PNG files to analyse. May also be directories or glob patterns.
+    #[clap(
+        required = true,
+        min_values = 1,
+        parse(from_os_str),
+        value_hint = ValueHint::AnyPath
+    )]
+    pub(crate) in_files: Vec<PathBuf>,
+
+    /// Report only files with at least this suspicion score, from 0 to 100.
+    #[clap(
+        long,
+        value_name = "SCORE",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u32).range(..=100)
+    )]
+    pub(crate) min_score: u32,
+
+    #[clap(flatten)]
+    pub(crate) batch: Batch,
+}
+
+/// Render a bit plane of a PNG file as a new greyscale PNG file.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Bitplane {
+    /// Path to the PNG file to render the bit plane of.
     #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
     pub(crate) in_file: PathBuf,
+
+    /// Path to save the rendered PNG file as.
+    #[clap(long, short, parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) out: PathBuf,
+
+    /// Channel to render the bit plane of.
+    #[clap(long, short, arg_enum, default_value = "red")]
+    pub(crate) channel: Channel,
+
+    /// Bit to render, where 0 is the least significant bit.
+    #[clap(
+        long,
+        short,
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(..16)
+    )]
+    pub(crate) bit: u8,
+
+    /// Render the given number of least significant bits of all colour channels instead,
+    /// stretched to the full range of brightness.
+    #[clap(
+        long,
+        value_name = "BITS",
+        conflicts_with_all = &["channel", "bit"],
+        value_parser = clap::value_parser!(u8).range(1..=8)
+    )]
+    pub(crate) amplify: Option<u8>,
+
+    #[clap(flatten)]
+    pub(crate) encoding: Encoding,
+}
+
+/// Channels of the colours of an image.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Channel {
+    #[clap(alias = "r")]
+    Red,
+
+    #[clap(alias = "g")]
+    Green,
+
+    #[clap(alias = "b")]
+    Blue,
+
+    #[clap(alias = "a")]
+    Alpha,
+}
+
+/// Create a new PNG file and hide a message in it, or store data in its pixels.
+#[derive(Debug, Parser)]
+#[clap(
+    setting = AppSettings::ArgRequiredElseHelp,
+)]
+pub(crate) struct Create {
+    /// Path to save the new PNG file as.
+    #[clap(parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub(crate) out_file: PathBuf,
+
+    /// Message to hide in the new image.
+    #[clap(required_unless_present = "data-image")]
+    pub(crate) message: Option<String>,
+
+    /// Width of the image in pixels. Defaults to 512, or to a square image with --data-image.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..=16384))]
+    pub(crate) width: Option<u32>,
+
+    /// Height of the image in pixels.
+    #[clap(
+        long,
+        default_value_t = 512,
+        value_parser = clap::value_parser!(u32).range(1..=16384),
+        conflicts_with = "data-image"
+    )]
+    pub(crate) height: u32,
+
+    /// Pattern to fill the image with.
+    #[clap(long, arg_enum, default_value = "noise", conflicts_with = "data-image")]
+    pub(crate) pattern: Pattern,
+
+    /// Store the bytes of this file in the samples of the image instead of hiding a message, so
+    /// that the image is a lossless container for the data. The height is chosen to fit the data.
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with = "message",
+        parse(from_os_str),
+        value_hint = ValueHint::FilePath
+    )]
+    pub(crate) data_image: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub(crate) hiding: Hiding,
+
+    #[clap(flatten)]
+    pub(crate) encoding: Encoding,
+}
+
+/// Patterns to fill new images with.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Pattern {
+    /// Random colours, which hide changes to the pixels best.
+    Noise,
+
+    /// A smooth blend of colours.
+    Gradient,
+
+    /// A single grey colour.
+    Solid,
 }
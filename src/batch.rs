@@ -0,0 +1,330 @@
+use std::path::{Path, PathBuf};
+
+use crate::args;
+use crate::error::Error;
+
+/// Expands the given paths into a sorted list of PNG files.
+/// Each path may be a file, a directory or a glob pattern. Directories are only searched for files
+/// with a `.png` extension, and their subdirectories are searched only if `recursive` is set.
+pub(crate) fn collect_files<'a, I>(paths: I, recursive: bool) -> Result<Vec<PathBuf>, Error>
+where
+    I: IntoIterator<Item = &'a PathBuf>,
+{
+    let mut files = Vec::new();
+    for path in paths {
+        if is_stdio(path) || path.is_file() {
+            files.push(path.clone());
+        } else if path.is_dir() {
+            collect_directory(path, recursive, &mut files)?;
+        } else {
+            let pattern = path.to_string_lossy();
+            let mut matched = false;
+            for entry in glob::glob(&pattern).map_err(|_| Error::PathNotFound(path.clone()))? {
+                let entry = entry.map_err(|error| Error::IoError(error.into()))?;
+                if entry.is_dir() {
+                    collect_directory(&entry, recursive, &mut files)?;
+                } else {
+                    files.push(entry);
+                }
+                matched = true;
+            }
+
+            if !matched {
+                return Err(Error::PathNotFound(path.clone()));
+            }
+        }
+    }
+
+    files.sort();
+    files.dedup();
+
+    Ok(files)
+}
+
+fn collect_directory(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                collect_directory(&path, recursive, files)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Name used in place of the file stem of standard input when naming output files after it.
+pub(crate) const STDIN_FILE_STEM: &str = "stdin";
+
+/// Returns `true` if the path is `-`, which stands for standard input when reading and standard
+/// output when writing.
+pub(crate) fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Returns the path to write the output for `in_file` to.
+/// If `out_file` is a directory, the output is saved in that directory with the same file name as
+/// `in_file`, or as `stdin.png` if `in_file` is standard input. If `out_file` is not specified,
+/// `in_file` is updated in place, or the output is written to standard output if `in_file` is
+/// standard input.
+pub(crate) fn out_file(in_file: &Path, out_file: Option<&Path>) -> PathBuf {
+    match out_file {
+        Some(out_file) if out_file.is_dir() => match in_file.file_name() {
+            _ if is_stdio(in_file) => out_file.join(format!("{STDIN_FILE_STEM}.png")),
+            Some(file_name) => out_file.join(file_name),
+            None => out_file.to_path_buf(),
+        },
+        Some(out_file) => out_file.to_path_buf(),
+        None => in_file.to_path_buf(),
+    }
+}
+
+/// Ensures that the outputs for multiple files are not all written to the same file.
+pub(crate) fn check_out_file(files: &[PathBuf], out_file: Option<&Path>) -> Result<(), Error> {
+    match out_file {
+        Some(out_file) if files.len() > 1 && !out_file.is_dir() => {
+            Err(Error::NotADirectory(out_file.to_path_buf()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Runs `process` on each of the files using a pool of worker threads.
+/// Errors for individual files are logged and processing continues with the remaining files. When
+/// processing more than one file, a summary is printed at the end.
+pub(crate) fn run<F>(files: &[PathBuf], opts: &args::Batch, process: F) -> Result<(), Error>
+where
+    F: Fn(&Path) -> Result<(), Error> + Sync,
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    // Report the error for a single file as is, without a summary
+    if let [file] = files {
+        return process(file).map_err(|error| error.in_file(file));
+    }
+
+    let jobs = opts
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .clamp(1, files.len().max(1));
+
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    log::debug!("Processing file '{}'", file.display());
+                    if let Err(error) = process(file) {
+                        log::error!("{}", error.in_file(file));
+                        failures.lock().unwrap().push(file);
+                    }
+                }
+            });
+        }
+    });
+
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort();
+
+    eprintln!(
+        "\nProcessed {} files: {} succeeded, {} failed",
+        files.len(),
+        files.len() - failures.len(),
+        failures.len()
+    );
+    for file in &failures {
+        eprintln!("    {}", file.display());
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::BatchFailed(failures.len(), files.len()))
+    }
+}
+
+/// Returns an empty directory for a test to write files to.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pngme-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates empty files at the given paths relative to `dir`, along with their parents.
+    fn create_files(dir: &Path, paths: &[&str]) {
+        for path in paths {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+    }
+
+    fn batch(jobs: usize) -> args::Batch {
+        args::Batch {
+            recursive: false,
+            jobs: Some(jobs),
+        }
+    }
+
+    #[test]
+    fn test_collect_files_directory() {
+        let dir = temp_dir("collect-directory");
+        create_files(&dir, &["b.png", "a.PNG", "notes.txt", "nested/c.png"]);
+
+        let files = collect_files([&dir], false).unwrap();
+        assert_eq!(files, [dir.join("a.PNG"), dir.join("b.png")]);
+
+        let files = collect_files([&dir], true).unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.join("a.PNG"),
+                dir.join("b.png"),
+                dir.join("nested/c.png")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_files_glob() {
+        let dir = temp_dir("collect-glob");
+        create_files(
+            &dir,
+            &["one.png", "two.png", "other.bin", "nested/three.png"],
+        );
+
+        // Files matching a glob pattern are kept whatever their extension, and duplicates are
+        // removed
+        let paths = [dir.join("*o*"), dir.join("one.png")];
+        let files = collect_files(&paths, false).unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.join("one.png"),
+                dir.join("other.bin"),
+                dir.join("two.png")
+            ]
+        );
+
+        // Directories matching a glob pattern are searched
+        let files = collect_files([&dir.join("nest*")], false).unwrap();
+        assert_eq!(files, [dir.join("nested/three.png")]);
+    }
+
+    #[test]
+    fn test_collect_files_not_found() {
+        let dir = temp_dir("collect-not-found");
+        create_files(&dir, &["one.png"]);
+
+        let paths = [dir.join("one.png"), dir.join("missing.png")];
+        assert!(matches!(
+            collect_files(&paths, false),
+            Err(Error::PathNotFound(path)) if path == dir.join("missing.png")
+        ));
+        assert!(matches!(
+            collect_files([&dir.join("*.jpg")], false),
+            Err(Error::PathNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_out_file() {
+        let dir = temp_dir("out-file");
+        let in_file = Path::new("images/photo.png");
+
+        assert_eq!(out_file(in_file, None), in_file);
+        assert_eq!(
+            out_file(in_file, Some(Path::new("out.png"))),
+            Path::new("out.png")
+        );
+        assert_eq!(out_file(in_file, Some(&dir)), dir.join("photo.png"));
+    }
+
+    #[test]
+    fn test_stdio() {
+        let dir = temp_dir("stdio");
+        let stdio = Path::new("-");
+
+        assert!(is_stdio(stdio));
+        assert!(!is_stdio(Path::new("-.png")));
+        assert!(!is_stdio(Path::new("./-")));
+
+        // Standard input is never looked up as a file
+        assert_eq!(
+            collect_files([&stdio.to_path_buf()], false).unwrap(),
+            [stdio]
+        );
+
+        assert_eq!(out_file(stdio, None), stdio);
+        assert_eq!(out_file(stdio, Some(stdio)), stdio);
+        assert_eq!(out_file(stdio, Some(&dir)), dir.join("stdin.png"));
+        assert_eq!(out_file(Path::new("in.png"), Some(stdio)), stdio);
+    }
+
+    #[test]
+    fn test_check_out_file() {
+        let dir = temp_dir("check-out-file");
+        let files = [PathBuf::from("a.png"), PathBuf::from("b.png")];
+
+        assert!(check_out_file(&files, None).is_ok());
+        assert!(check_out_file(&files, Some(&dir)).is_ok());
+        assert!(check_out_file(&files[..1], Some(Path::new("out.png"))).is_ok());
+        assert!(matches!(
+            check_out_file(&files, Some(Path::new("out.png"))),
+            Err(Error::NotADirectory(_))
+        ));
+    }
+
+    #[test]
+    fn test_run_continues_after_errors() {
+        use std::sync::Mutex;
+
+        let files: Vec<PathBuf> = ["a.png", "b.png", "c.png", "d.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let processed = Mutex::new(Vec::new());
+
+        let result = run(&files, &batch(2), |file| {
+            processed.lock().unwrap().push(file.to_path_buf());
+            if file == Path::new("b.png") || file == Path::new("d.png") {
+                Err(Error::PayloadNotFound)
+            } else {
+                Ok(())
+            }
+        });
+
+        assert!(matches!(result, Err(Error::BatchFailed(2, 4))));
+        let mut processed = processed.into_inner().unwrap();
+        processed.sort();
+        assert_eq!(processed, files);
+    }
+
+    #[test]
+    fn test_run_single_file() {
+        let files = [PathBuf::from("a.png")];
+
+        assert!(run(&files, &batch(1), |_| Ok(())).is_ok());
+        assert!(matches!(
+            run(&files, &batch(1), |_| Err(Error::PayloadNotFound)),
+            Err(Error::InFile { path, source })
+                if path == files[0] && matches!(*source, Error::PayloadNotFound)
+        ));
+    }
+}
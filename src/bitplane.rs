@@ -0,0 +1,220 @@
+use crate::error::Error;
+use crate::image::{ColourType, Header, Image, Transparency};
+
+/// A channel of the colours of an image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl Channel {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Red => "red",
+            Self::Green => "green",
+            Self::Blue => "blue",
+            Self::Alpha => "alpha",
+        }
+    }
+}
+
+/// Renders a single bit plane of a channel of the image as an 8-bit greyscale image, with white
+/// pixels where the bit is set and black pixels where it isn't. Bit 0 is the least significant
+/// bit.
+pub(crate) fn bit_plane(image: &Image, channel: Channel, bit: u8) -> Result<Image, Error> {
+    let (samples, bit_depth) = channel_samples(image, channel)?;
+    if bit >= bit_depth {
+        return Err(Error::InvalidBitPlane(format!(
+            "bit {bit} doesn't exist in {bit_depth}-bit samples"
+        )));
+    }
+
+    let samples = samples
+        .into_iter()
+        .map(|sample| if sample >> bit & 1 == 1 { 255 } else { 0 })
+        .collect();
+
+    Ok(Image::new(
+        output_header(image, ColourType::Greyscale),
+        samples,
+    ))
+}
+
+/// Renders the least significant `bits` of each colour channel of the image, stretched to the full
+/// range of 8-bit samples, so that patterns in them become visible. Greyscale images are rendered
+/// as greyscale, all other images as RGB.
+pub(crate) fn amplify(image: &Image, bits: u8) -> Result<Image, Error> {
+    let (colour_type, channels) = match image.header().colour_type {
+        ColourType::Greyscale | ColourType::GreyscaleAlpha => {
+            (ColourType::Greyscale, &[Channel::Red][..])
+        }
+        ColourType::Rgb | ColourType::Rgba | ColourType::Indexed => (
+            ColourType::Rgb,
+            &[Channel::Red, Channel::Green, Channel::Blue][..],
+        ),
+    };
+
+    let planes = channels
+        .iter()
+        .map(|&channel| channel_samples(image, channel))
+        .collect::<Result<Vec<_>, _>>()?;
+    let bit_depth = planes[0].1;
+    if bits == 0 || bits > bit_depth.min(8) {
+        return Err(Error::InvalidBitPlane(format!(
+            "can't amplify {bits} bits of {bit_depth}-bit samples"
+        )));
+    }
+
+    let mask = (1_u32 << bits) - 1;
+    let samples = (0..planes[0].0.len())
+        .flat_map(|i| planes.iter().map(move |(samples, _)| samples[i]))
+        .map(|sample| {
+            #[allow(clippy::cast_possible_truncation)]
+            let amplified = ((u32::from(sample) & mask) * 255 / mask) as u16;
+            amplified
+        })
+        .collect();
+
+    Ok(Image::new(output_header(image, colour_type), samples))
+}
+
+/// Returns the samples of the channel of each pixel of the image in row-major order, and their
+/// bit depth. Greyscale images have the same samples in the red, green and blue channels, and the
+/// channels of indexed-colour images are those of the palette entries.
+fn channel_samples(image: &Image, channel: Channel) -> Result<(Vec<u16>, u8), Error> {
+    let header = image.header();
+    let no_alpha =
+        || Error::InvalidBitPlane(format!("the image has no {} channel", channel.name()));
+
+    if header.colour_type == ColourType::Indexed {
+        let palette = image.palette();
+        let alpha = match image.transparency() {
+            Some(Transparency::Palette(alpha)) => alpha.as_slice(),
+            _ if channel == Channel::Alpha => return Err(no_alpha()),
+            _ => &[],
+        };
+        let samples = image
+            .samples()
+            .iter()
+            .map(|&index| {
+                let index = usize::from(index);
+                u16::from(match channel {
+                    Channel::Red => palette[index][0],
+                    Channel::Green => palette[index][1],
+                    Channel::Blue => palette[index][2],
+                    Channel::Alpha => alpha.get(index).copied().unwrap_or(255),
+                })
+            })
+            .collect();
+
+        return Ok((samples, 8));
+    }
+
+    let index = match (header.colour_type, channel) {
+        (
+            ColourType::Greyscale | ColourType::GreyscaleAlpha,
+            Channel::Red | Channel::Green | Channel::Blue,
+        )
+        | (ColourType::Rgb | ColourType::Rgba, Channel::Red) => 0,
+        (ColourType::GreyscaleAlpha, Channel::Alpha)
+        | (ColourType::Rgb | ColourType::Rgba, Channel::Green) => 1,
+        (ColourType::Rgb | ColourType::Rgba, Channel::Blue) => 2,
+        (ColourType::Rgba, Channel::Alpha) => 3,
+        _ => return Err(no_alpha()),
+    };
+    let samples = image
+        .samples()
+        .iter()
+        .skip(index)
+        .step_by(image.channels())
+        .copied()
+        .collect();
+
+    Ok((samples, header.bit_depth))
+}
+
+/// Returns the header of an 8-bit, non-interlaced image of the same dimensions as the image.
+fn output_header(image: &Image, colour_type: ColourType) -> Header {
+    Header {
+        width: image.header().width,
+        height: image.header().height,
+        bit_depth: 8,
+        colour_type,
+        interlaced: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_plane() {
+        let image = Image::from_samples(2, 1, ColourType::Rgb, 8, vec![1, 2, 3, 4, 5, 6]);
+
+        let plane = bit_plane(&image, Channel::Red, 0).unwrap();
+        assert_eq!(plane.header().colour_type, ColourType::Greyscale);
+        assert_eq!(plane.samples(), [255, 0]);
+
+        let plane = bit_plane(&image, Channel::Green, 1).unwrap();
+        assert_eq!(plane.samples(), [255, 0]);
+
+        let plane = bit_plane(&image, Channel::Blue, 2).unwrap();
+        assert_eq!(plane.samples(), [0, 255]);
+    }
+
+    #[test]
+    fn test_bit_plane_16_bit() {
+        let image = Image::from_samples(
+            2,
+            1,
+            ColourType::GreyscaleAlpha,
+            16,
+            vec![0x8000, 1, 0, 0xffff],
+        );
+
+        let plane = bit_plane(&image, Channel::Blue, 15).unwrap();
+        assert_eq!(plane.samples(), [255, 0]);
+
+        let plane = bit_plane(&image, Channel::Alpha, 0).unwrap();
+        assert_eq!(plane.samples(), [255, 255]);
+    }
+
+    #[test]
+    fn test_invalid_bit_plane() {
+        let image = Image::from_samples(2, 1, ColourType::Rgb, 8, vec![0; 6]);
+        assert!(matches!(
+            bit_plane(&image, Channel::Red, 8),
+            Err(Error::InvalidBitPlane(_))
+        ));
+        assert!(matches!(
+            bit_plane(&image, Channel::Alpha, 0),
+            Err(Error::InvalidBitPlane(_))
+        ));
+    }
+
+    #[test]
+    fn test_amplify() {
+        let image = Image::from_samples(
+            2,
+            1,
+            ColourType::Rgba,
+            8,
+            vec![1, 2, 3, 0, 252, 253, 254, 255],
+        );
+
+        let amplified = amplify(&image, 1).unwrap();
+        assert_eq!(amplified.header().colour_type, ColourType::Rgb);
+        assert_eq!(amplified.samples(), [255, 0, 255, 0, 255, 0]);
+
+        let amplified = amplify(&image, 2).unwrap();
+        assert_eq!(amplified.samples(), [85, 170, 255, 0, 85, 170]);
+
+        let image = Image::from_samples(2, 1, ColourType::Greyscale, 1, vec![0, 1]);
+        assert_eq!(amplify(&image, 1).unwrap().samples(), [0, 255]);
+        assert!(amplify(&image, 2).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::io::BufRead;
+
+use crate::error::Error;
+use crate::png::Png;
+
+/// Maximum length of the data of a chunk allowed by the PNG specification.
+const MAX_CHUNK_LENGTH: u32 = (1 << 31) - 1;
+
+/// A PNG image recovered from a larger blob of binary data.
+pub(crate) struct CarvedPng {
+    /// Byte offset of the PNG header within the blob.
+    pub(crate) offset: usize,
+
+    /// Number of bytes the PNG spans within the blob, from the header up to and including the
+    /// `IEND` chunk.
+    pub(crate) length: usize,
+
+    pub(crate) png: Png,
+}
+
+/// Searches the data read from `reader` for PNG images, passing each one to `found` in order.
+/// Every occurrence of the standard PNG header is parsed forward chunk by chunk until an `IEND`
+/// chunk is found. Candidates with invalid chunks or CRC mismatches are skipped.
+/// The data is scanned through a sliding window, so only the candidate being parsed is kept in
+/// memory rather than all of the data. Returns the number of PNG images found.
+pub(crate) fn carve<R, F>(mut reader: R, mut found: F) -> Result<usize, Error>
+where
+    R: BufRead,
+    F: FnMut(CarvedPng) -> Result<(), Error>,
+{
+    let mut window = Vec::new();
+    // Offset of the start of the window within the data
+    let mut window_offset = 0;
+    let mut eof = false;
+    let mut count = 0;
+    let mut ptr = 0;
+    loop {
+        let Some(position) = find_header(&window[ptr..]) else {
+            if eof {
+                return Ok(count);
+            }
+
+            // Keep the bytes a header straddling the end of the window could start at
+            ptr = ptr.max(window.len().saturating_sub(Png::STANDARD_HEADER.len() - 1));
+            window.drain(..ptr);
+            window_offset += ptr;
+            ptr = 0;
+            eof = !fill(&mut reader, &mut window)?;
+            continue;
+        };
+
+        let start = ptr + position;
+        let end = match candidate_length(&window[start..]) {
+            Candidate::Incomplete if !eof => {
+                window.drain(..start);
+                window_offset += start;
+                ptr = 0;
+                eof = !fill(&mut reader, &mut window)?;
+                continue;
+            }
+            Candidate::Complete(length) => start + length,
+            Candidate::Incomplete | Candidate::Invalid => window.len(),
+        };
+
+        let offset = window_offset + start;
+        match Png::parse_prefix(&window[start..end]) {
+            Ok((png, length)) if png.chunk_by_type("IEND").is_some() => {
+                found(CarvedPng {
+                    offset,
+                    length,
+                    png,
+                })?;
+                count += 1;
+                ptr = start + length;
+            }
+            Ok(_) => {
+                log::debug!("Skipping PNG header at offset {offset}: IEND chunk not found");
+                ptr = start + 1;
+            }
+            Err(error) => {
+                log::debug!("Skipping PNG header at offset {offset}: {error}");
+                ptr = start + 1;
+            }
+        }
+    }
+}
+
+/// How far a PNG candidate extends, judging by the lengths of its chunks.
+enum Candidate {
+    /// The candidate spans this many bytes, up to and including its `IEND` chunk.
+    Complete(usize),
+
+    /// More bytes are needed to reach the `IEND` chunk.
+    Incomplete,
+
+    /// A chunk can't be valid, so parsing the candidate fails without reading any further.
+    Invalid,
+}
+
+/// Walks the chunks of the PNG candidate at the start of `bytes` by their lengths, without
+/// checking their data, to find out how many bytes the candidate needs.
+fn candidate_length(bytes: &[u8]) -> Candidate {
+    let mut ptr = Png::STANDARD_HEADER.len();
+    while let Some(prefix) = bytes.get(ptr..ptr + 8) {
+        let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+        let chunk_type = &prefix[4..];
+        if length > MAX_CHUNK_LENGTH || !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+            return Candidate::Invalid;
+        }
+
+        ptr += 12 + length as usize;
+        if chunk_type == b"IEND" {
+            return if ptr <= bytes.len() {
+                Candidate::Complete(ptr)
+            } else {
+                Candidate::Incomplete
+            };
+        }
+    }
+
+    Candidate::Incomplete
+}
+
+/// Reads more data from `reader` into `window`. Returns `false` at the end of the data.
+fn fill<R: BufRead>(reader: &mut R, window: &mut Vec<u8>) -> Result<bool, Error> {
+    loop {
+        match reader.fill_buf() {
+            Ok(bytes) => {
+                let length = bytes.len();
+                window.extend_from_slice(bytes);
+                reader.consume(length);
+
+                return Ok(length > 0);
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+fn find_header(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .windows(Png::STANDARD_HEADER.len())
+        .position(|window| window == Png::STANDARD_HEADER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_png_bytes(message: &str) -> Vec<u8> {
+        let chunk = |chunk_type: &str, data: &str| {
+            Chunk::new(
+                ChunkType::from_str(chunk_type).unwrap(),
+                data.as_bytes().to_vec(),
+            )
+        };
+
+        Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunk("IHDR", "").as_bytes())
+            .chain(chunk("ruSt", message).as_bytes())
+            .chain(chunk("IEND", "").as_bytes())
+            .collect()
+    }
+
+    fn carve_all<R: BufRead>(reader: R) -> Vec<CarvedPng> {
+        let mut carved = Vec::new();
+        let count = carve(reader, |png| {
+            carved.push(png);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, carved.len());
+
+        carved
+    }
+
+    #[test]
+    fn test_carve_multiple_pngs() {
+        let first = testing_png_bytes("first");
+        let second = testing_png_bytes("second");
+
+        let mut blob = b"some garbage".to_vec();
+        blob.extend_from_slice(&first);
+        blob.extend_from_slice(b"more garbage");
+        blob.extend_from_slice(&second);
+
+        let carved = carve_all(blob.as_slice());
+        assert_eq!(carved.len(), 2);
+
+        assert_eq!(carved[0].offset, 12);
+        assert_eq!(carved[0].length, first.len());
+        assert_eq!(carved[0].png.as_bytes(), first);
+
+        assert_eq!(carved[1].offset, 24 + first.len());
+        assert_eq!(carved[1].length, second.len());
+        let chunk = carved[1].png.chunk_by_type("ruSt").unwrap();
+        assert_eq!(chunk.data_as_string().unwrap(), "second");
+    }
+
+    #[test]
+    fn test_carve_skips_corrupted_png() {
+        let mut corrupted = testing_png_bytes("corrupted");
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        let valid = testing_png_bytes("valid");
+
+        let mut blob = corrupted;
+        blob.extend_from_slice(&valid);
+
+        let carved = carve_all(blob.as_slice());
+        assert_eq!(carved.len(), 1);
+        assert_eq!(carved[0].png.as_bytes(), valid);
+    }
+
+    #[test]
+    fn test_carve_skips_truncated_png() {
+        let png = testing_png_bytes("truncated");
+        let carved = carve_all(&png[..png.len() - 4]);
+        assert!(carved.is_empty());
+    }
+
+    #[test]
+    fn test_carve_across_reads() {
+        let png = testing_png_bytes("split");
+        let mut blob = b"garbage".to_vec();
+        blob.extend_from_slice(&png);
+        blob.extend_from_slice(&Png::STANDARD_HEADER);
+        blob.extend_from_slice(&png);
+
+        // Reading a few bytes at a time splits the headers and chunks across reads
+        let carved = carve_all(std::io::BufReader::with_capacity(3, blob.as_slice()));
+        assert_eq!(carved.len(), 2);
+        assert_eq!(carved[0].offset, 7);
+        assert_eq!(carved[0].png.as_bytes(), png);
+        assert_eq!(carved[1].offset, 15 + png.len());
+        assert_eq!(carved[1].png.as_bytes(), png);
+    }
+
+    #[test]
+    fn test_carve_stops_at_invalid_chunk_length() {
+        let mut blob = Png::STANDARD_HEADER.to_vec();
+        blob.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        blob.extend_from_slice(b"ruSt");
+        let png = testing_png_bytes("valid");
+        blob.extend_from_slice(&png);
+
+        let carved = carve_all(blob.as_slice());
+        assert_eq!(carved.len(), 1);
+        assert_eq!(carved[0].offset, 16);
+    }
+}
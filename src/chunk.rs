@@ -2,9 +2,11 @@ use crc::{Crc, CRC_32_ISO_HDLC};
 
 use crate::{chunk_type::ChunkType, error::Error};
 
-const CRC_32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+pub(crate) const CRC_32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
 /// A PNG chunk.
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Eq, PartialEq)]
 pub(crate) struct Chunk {
     /// A 4-byte unsigned integer depicting the number of bytes in the chunk's data field.
     /// The length counts only the data field, not itself, the chunk type code, or the CRC.
@@ -39,8 +41,7 @@ impl Chunk {
     }
 
     /// Returns the number of bytes in the chunk's data field.
-    #[allow(dead_code)]
-    fn length(&self) -> u32 {
+    pub(crate) fn length(&self) -> u32 {
         self.length
     }
 
@@ -50,11 +51,15 @@ impl Chunk {
     }
 
     /// Returns the chunk's CRC.
-    #[allow(dead_code)]
     pub(crate) fn crc(&self) -> u32 {
         self.crc
     }
 
+    /// Returns the chunk's data.
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.chunk_data
+    }
+
     /// Tries to convert the chunk's data and return it as a [`String`](String).
     pub(crate) fn data_as_string(&self) -> Result<String, Error> {
         Ok(String::from_utf8(self.chunk_data.clone())?)
@@ -76,14 +81,37 @@ impl std::convert::TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let length = u32::from_be_bytes(value[0..4].try_into()?);
-        let chunk_type: [u8; 4] = value[4..8].try_into()?;
-        let chunk_type = ChunkType::try_from(chunk_type)?;
-        let chunk_data = value[8..8 + length as usize].to_vec();
-        let crc = u32::from_be_bytes(value[8 + length as usize..].try_into()?);
+        if value.len() < 12 {
+            return Err(Error::UnexpectedEof {
+                length: value.len(),
+                expected: 12,
+            });
+        }
 
-        if CRC_32.checksum(&value[4..8 + length as usize]) != crc {
-            return Err(Error::CrcMismatch);
+        let length = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+        if value.len() != 12 + length as usize {
+            return Err(Error::UnexpectedEof {
+                length: value.len(),
+                expected: 12 + length as usize,
+            });
+        }
+
+        let chunk_type = ChunkType::try_from([value[4], value[5], value[6], value[7]])?;
+        let chunk_data = value[8..8 + length as usize].to_vec();
+        let crc_offset = 8 + length as usize;
+        let crc = u32::from_be_bytes([
+            value[crc_offset],
+            value[crc_offset + 1],
+            value[crc_offset + 2],
+            value[crc_offset + 3],
+        ]);
+
+        let actual = CRC_32.checksum(&value[4..crc_offset]);
+        if actual != crc {
+            return Err(Error::CrcMismatch {
+                expected: crc,
+                actual,
+            });
         }
 
         Ok(Chunk {
@@ -202,10 +230,17 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert!(matches!(
+            chunk,
+            Err(Error::CrcMismatch {
+                expected: 2_882_656_333,
+                actual: 2_882_656_334
+            })
+        ));
     }
 
     #[test]
+    #[allow(clippy::uninlined_format_args)]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
         let chunk_type = "RuSt".as_bytes();
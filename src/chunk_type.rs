@@ -1,7 +1,7 @@
 use crate::error::Error;
 
 /// A 4-byte chunk type code. Must consist of uppercase or lowercase ASCII letters only.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct ChunkType {
     /// Four bits of the type code, namely bit 5 (value 32) of each byte, are used to convey chunk
     /// properties.
@@ -85,6 +85,66 @@ impl ChunkType {
 
         self.safe_to_copy.bitand(32_u8) == 32_u8
     }
+
+    /// Returns `true` if the chunk is safe to be modified.
+    /// A chunk is considered safe to be modified if it is ancillary, private, has a valid reserved
+    /// bit, and is safe-to-copy.
+    pub(crate) fn is_modifiable(&self) -> bool {
+        self.is_valid()
+            && !self.is_critical()
+            && !self.is_public()
+            && self.is_reserved_bit_valid()
+            && self.is_safe_to_copy()
+    }
+
+    /// Returns a chunk type which can be used for messages, formed from the first four ASCII
+    /// letters of `seed`. The letters are repeated if there are fewer than four of them.
+    /// Returns `None` if `seed` contains no ASCII letters.
+    pub(crate) fn from_seed(seed: &str) -> Option<Self> {
+        let letters: Vec<u8> = seed.bytes().filter(u8::is_ascii_alphabetic).collect();
+        if letters.is_empty() {
+            return None;
+        }
+
+        let mut letters = letters.iter().copied().cycle();
+        let mut next = || letters.next().unwrap_or(b'a');
+        let chunk_type = Self {
+            ancillary: next(),
+            private: next(),
+            reserved: next(),
+            safe_to_copy: next(),
+        };
+
+        Some(chunk_type.to_modifiable())
+    }
+
+    /// Returns a random chunk type which can be used for messages.
+    pub(crate) fn random() -> Self {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let mut next = || rng.gen_range(b'a'..=b'z');
+        let chunk_type = Self {
+            ancillary: next(),
+            private: next(),
+            reserved: next(),
+            safe_to_copy: next(),
+        };
+
+        chunk_type.to_modifiable()
+    }
+
+    /// Returns the closest chunk type which can be used for messages, i.e., one which is
+    /// ancillary, private, has a valid reserved bit and is safe-to-copy. The letters are kept and
+    /// only their case is changed.
+    pub(crate) fn to_modifiable(&self) -> Self {
+        Self {
+            ancillary: self.ancillary.to_ascii_lowercase(),
+            private: self.private.to_ascii_lowercase(),
+            reserved: self.reserved.to_ascii_uppercase(),
+            safe_to_copy: self.safe_to_copy.to_ascii_lowercase(),
+        }
+    }
 }
 
 impl std::convert::TryFrom<[u8; 4]> for ChunkType {
@@ -92,9 +152,7 @@ impl std::convert::TryFrom<[u8; 4]> for ChunkType {
 
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
         if !value.iter().all(u8::is_ascii_alphabetic) {
-            return Err(Error::InvalidChunkType(
-                std::str::from_utf8(&value).unwrap().to_string(),
-            ));
+            return Err(Error::InvalidChunkType(value.escape_ascii().to_string()));
         }
 
         Ok(Self {
@@ -127,7 +185,7 @@ impl std::str::FromStr for ChunkType {
 impl std::fmt::Display for ChunkType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match std::str::from_utf8(&self.bytes()) {
-            Ok(s) => write!(f, "{}", s),
+            Ok(s) => write!(f, "{s}"),
             Err(_) => Err(std::fmt::Error),
         }
     }
@@ -218,6 +276,49 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_invalid_chunk_type_from_bytes() {
+        let chunk = ChunkType::try_from([82, 0xff, 83, 116]);
+        assert!(matches!(chunk, Err(Error::InvalidChunkType(s)) if s == "R\\xffSt"));
+    }
+
+    #[test]
+    pub fn test_chunk_type_to_modifiable() {
+        let chunk = ChunkType::from_str("RUst").unwrap();
+        assert_eq!(&chunk.to_modifiable().to_string(), "ruSt");
+
+        let chunk = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(chunk.to_modifiable(), chunk);
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_modifiable() {
+        assert!(ChunkType::from_str("ruSt").unwrap().is_modifiable());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_modifiable());
+        assert!(!ChunkType::from_str("rUSt").unwrap().is_modifiable());
+        assert!(!ChunkType::from_str("rust").unwrap().is_modifiable());
+        assert!(!ChunkType::from_str("ruST").unwrap().is_modifiable());
+    }
+
+    #[test]
+    pub fn test_chunk_type_from_seed() {
+        let chunk = ChunkType::from_seed("SECRET").unwrap();
+        assert_eq!(&chunk.to_string(), "seCr");
+        assert!(chunk.is_modifiable());
+
+        let chunk = ChunkType::from_seed("a-b").unwrap();
+        assert_eq!(&chunk.to_string(), "abAb");
+
+        assert!(ChunkType::from_seed("1234").is_none());
+    }
+
+    #[test]
+    pub fn test_random_chunk_type_is_modifiable() {
+        for _ in 0..100 {
+            assert!(ChunkType::random().is_modifiable());
+        }
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -225,6 +326,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::uninlined_format_args, clippy::no_effect_underscore_binding)]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
         let chunk_type_2: ChunkType = FromStr::from_str("RuSt").unwrap();
@@ -1,129 +1,599 @@
 use std::fs::{File, OpenOptions};
+use std::path::Path;
 
+use crate::analyze;
 use crate::args;
+use crate::batch;
+use crate::bitplane::{self, Channel};
+use crate::carve;
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
+use crate::compare;
+use crate::create;
+use crate::crypto;
+use crate::diff::{self, ChunkChange, LineChange};
+use crate::envelope::{ContentType, Envelope};
 use crate::error::Error;
+use crate::filter;
+use crate::idat;
+use crate::image::{self, FilterStrategy, FilterType, Image};
+use crate::lsb;
+use crate::manifest::{
+    ExtractManifest, ExtractedChunk, ImportManifest, Payload, PayloadEncoding, Placement,
+};
+use crate::palette;
 use crate::png::Png;
+use crate::text::{self, TextualData};
+use crate::zlib;
 
-impl ChunkType {
-    /// Returns `true` if the chunk is safe to be modified.
-    /// A chunk is considered safe to be modified if it is ancillary, private, has a valid reserved
-    /// bit, and is safe-to-copy.
-    fn is_modifiable(&self) -> bool {
-        self.is_valid()
-            && !self.is_critical()
-            && !self.is_public()
-            && self.is_reserved_bit_valid()
-            && self.is_safe_to_copy()
+impl args::Filter {
+    fn strategy(self) -> FilterStrategy {
+        match self {
+            Self::None => FilterStrategy::Fixed(FilterType::None),
+            Self::Sub => FilterStrategy::Fixed(FilterType::Sub),
+            Self::Up => FilterStrategy::Fixed(FilterType::Up),
+            Self::Average => FilterStrategy::Fixed(FilterType::Average),
+            Self::Paeth => FilterStrategy::Fixed(FilterType::Paeth),
+            Self::Adaptive => FilterStrategy::Adaptive,
+        }
+    }
+}
+
+impl args::Encoding {
+    /// Replaces the image data of the PNG with the encoded image.
+    fn write_image(&self, png: &mut Png, image: &Image) {
+        let image_data = image.encode(self.filter.strategy(), self.level);
+        png.replace_image_data(&image_data, self.idat_size as usize);
+    }
+
+    /// Creates a new PNG containing only the encoded image.
+    fn new_png(&self, image: &Image) -> Png {
+        let mut png = Png::from_chunks(vec![
+            image.header().to_chunk(),
+            Chunk::new(
+                ChunkType::try_from(*b"IEND").expect("IEND is a valid chunk type"),
+                Vec::new(),
+            ),
+        ]);
+        self.write_image(&mut png, image);
+
+        png
+    }
+}
+
+impl args::Channel {
+    fn channel(self) -> Channel {
+        match self {
+            Self::Red => Channel::Red,
+            Self::Green => Channel::Green,
+            Self::Blue => Channel::Blue,
+            Self::Alpha => Channel::Alpha,
+        }
+    }
+}
+
+impl args::PixelSelection {
+    fn selection(&self) -> lsb::Selection {
+        lsb::Selection {
+            skip_flat: self.skip_flat,
+            skip_transparent: self.skip_transparent,
+        }
+    }
+}
+
+impl args::StripPreset {
+    /// Chunks which affect how the colours of the image are displayed.
+    const COLOUR_MANAGEMENT_CHUNKS: [&'static str; 9] = [
+        "tRNS", "gAMA", "cHRM", "sRGB", "iCCP", "sBIT", "cICP", "mDCV", "cLLI",
+    ];
+
+    /// Returns `true` if the preset keeps chunks of the given type.
+    fn keeps(self, chunk_type: &ChunkType) -> bool {
+        if chunk_type.is_critical() {
+            return true;
+        }
+
+        match self {
+            Self::KeepOnlyCritical => false,
+            Self::KeepColourManagement => {
+                Self::COLOUR_MANAGEMENT_CHUNKS.contains(&chunk_type.to_string().as_str())
+            }
+            Self::RemovePrivateOnly => chunk_type.is_public(),
+        }
     }
 }
 
-pub(crate) fn encode(opts: args::Encode) -> Result<(), Error> {
-    use std::io::{Read, Write};
+/// Reads the file at the given path, or standard input if the path is `-`.
+fn read_bytes(path: &Path) -> Result<Vec<u8>, Error> {
+    use std::io::Read;
 
-    let out_file = if opts.out_file.is_none() {
-        opts.in_file.clone()
+    let mut bytes = Vec::new();
+    let result = if batch::is_stdio(path) {
+        std::io::stdin().lock().read_to_end(&mut bytes)
     } else {
-        opts.out_file.unwrap()
+        File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
     };
-    let in_file = opts.in_file;
-    let chunk_type = opts.chunk_type;
-    let message = opts.message;
+    result.map_err(|error| Error::from(error).in_file(path))?;
+
+    Ok(bytes)
+}
 
-    let mut in_file = File::open(in_file)?;
-    let mut png_bytes = Vec::new();
-    in_file.read_to_end(&mut png_bytes)?;
-    let mut png = Png::try_from(png_bytes.as_slice())?;
+/// Reads and parses the PNG file at the given path, or standard input if the path is `-`.
+fn read_png(path: &Path) -> Result<Png, Error> {
+    let png_bytes = read_bytes(path)?;
 
-    // Allow only safe-to-modify chunks
-    if !chunk_type.is_modifiable() {
-        return Err(Error::UnmodifiableChunkType(chunk_type));
+    Png::try_from(png_bytes.as_slice()).map_err(|error| error.in_file(path))
+}
+
+/// Writes the PNG to the file at the given path, overwriting the file if it exists. If the path is
+/// `-`, the PNG is written to standard output instead.
+fn write_png(path: &Path, png_bytes: &[u8]) -> Result<(), Error> {
+    write_png_bytes(path, png_bytes, false).map_err(|error| Error::from(error).in_file(path))
+}
+
+/// Writes the PNG like [`write_png`](write_png), but to a temporary file which then replaces the
+/// file at the given path, so that the file is never left partially written. The permissions of
+/// the replaced file are kept.
+fn write_png_atomically(path: &Path, png_bytes: &[u8]) -> Result<(), Error> {
+    write_png_bytes(path, png_bytes, true).map_err(|error| Error::from(error).in_file(path))
+}
+
+fn write_png_bytes(path: &Path, png_bytes: &[u8], atomically: bool) -> std::io::Result<()> {
+    use std::io::Write;
+
+    if batch::is_stdio(path) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(png_bytes)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    if !atomically {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(path)?;
+        return file.write_all(png_bytes);
+    }
+
+    let mut temp_file_name = std::ffi::OsString::from(".");
+    temp_file_name.push(path.file_name().unwrap_or_default());
+    temp_file_name.push(".pngme-tmp");
+    let temp_path = path.with_file_name(temp_file_name);
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&temp_path)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(png_bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        std::fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+/// Returns the stream to print status messages to after writing a PNG to `out_file`.
+/// Status messages go to standard error when the PNG itself is written to standard output. The
+/// stream is locked so that output for different files is not interleaved.
+fn status_output(out_file: &Path) -> Box<dyn std::io::Write> {
+    if batch::is_stdio(out_file) {
+        Box::new(std::io::stderr().lock())
+    } else {
+        Box::new(std::io::stdout().lock())
     }
+}
+
+/// Returns copies of the chunks the pixels of the PNG depend on, for
+/// [`verify_pixels`](verify_pixels).
+fn pixel_chunks(png: &Png) -> Vec<Chunk> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| {
+            matches!(
+                chunk.chunk_type().to_string().as_str(),
+                "IHDR" | "PLTE" | "tRNS" | "IDAT"
+            )
+        })
+        .map(|chunk| Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec()))
+        .collect()
+}
 
-    // Disallow duplicate chunk to be added
-    if png.chunk_by_type(&chunk_type.to_string()).is_some() {
-        return Err(Error::ChunkTypeExists(chunk_type));
+/// Checks that the pixels of the PNG about to be written are the same as those of the original
+/// image, given the chunks of the input file returned by [`pixel_chunks`](pixel_chunks). The
+/// images are only decoded and compared if those chunks changed. Input files whose image can't be
+/// decoded aren't checked.
+fn verify_pixels(original: Vec<Chunk>, png: &Png, in_file: &Path) -> Result<(), Error> {
+    if original == pixel_chunks(png) {
+        return Ok(());
     }
 
-    png.append_chunk(Chunk::new(chunk_type, message.as_bytes().to_vec()))?;
+    let original = match Image::decode(&Png::from_chunks(original)) {
+        Ok(original) => original,
+        Err(error) => {
+            log::debug!(
+                "Not verifying the pixels of file '{}': {}",
+                in_file.display(),
+                error
+            );
+            return Ok(());
+        }
+    };
 
-    let mut out_file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(out_file)?;
-    out_file.write_all(&png.as_bytes())?;
+    let image = Image::decode(png)
+        .map_err(|error| Error::PixelsChanged(format!("the image can't be decoded ({error})")))?;
+    let comparison = compare::compare(&original, &image)
+        .map_err(|error| Error::PixelsChanged(error.to_string()))?;
+    if !comparison.is_identical() {
+        return Err(Error::PixelsChanged(comparison.to_string()));
+    }
 
     Ok(())
 }
 
-pub(crate) fn decode(opts: args::Decode) -> Result<(), Error> {
-    use std::io::Read;
+/// Where a message is stored in a PNG file.
+enum MessageLocation {
+    /// A chunk of its own.
+    Chunk(ChunkType),
+
+    /// A standard textual chunk, camouflaged as metadata.
+    Camouflaged {
+        camouflage: args::Camouflage,
+        keyword: String,
+    },
+}
 
-    let in_file = opts.in_file;
-    let chunk_type = opts.chunk_type;
+/// Returns where the message is stored given the chunk type, deriving the chunk type from the
+/// secret if none is given. A secret can't be combined with any other chunk type, and a keyword
+/// can only be given for `zTXt`.
+fn message_location(
+    chunk_type: Option<&args::MessageChunkType>,
+    secret: &args::Secret,
+    keyword: Option<&str>,
+) -> Result<MessageLocation, Error> {
+    if keyword.is_some()
+        && !matches!(
+            chunk_type,
+            Some(args::MessageChunkType::Camouflaged(
+                args::Camouflage::CompressedText
+            ))
+        )
+    {
+        return Err(Error::IncompatibleOptions(
+            "a keyword can only be given for zTXt".to_string(),
+        ));
+    }
 
-    // Allow only safe-to-modify chunks
-    if !chunk_type.is_modifiable() {
-        return Err(Error::UnmodifiableChunkType(chunk_type));
+    match (chunk_type, &secret.secret) {
+        (
+            Some(args::MessageChunkType::Given(_) | args::MessageChunkType::Camouflaged(_)),
+            Some(_),
+        ) => Err(Error::IncompatibleOptions(
+            "a secret can only be used to derive the chunk type, use 'derive' as the chunk type"
+                .to_string(),
+        )),
+        (Some(args::MessageChunkType::Given(chunk_type)), None) => {
+            Ok(MessageLocation::Chunk(chunk_type.clone()))
+        }
+        (Some(args::MessageChunkType::Camouflaged(camouflage)), None) => {
+            let keyword = match camouflage {
+                args::Camouflage::CompressedText => keyword.unwrap_or(text::DEFAULT_KEYWORD),
+                args::Camouflage::Xmp => text::XMP_KEYWORD,
+            };
+            text::check_keyword(keyword)?;
+            Ok(MessageLocation::Camouflaged {
+                camouflage: *camouflage,
+                keyword: keyword.to_string(),
+            })
+        }
+        (Some(args::MessageChunkType::Derived) | None, Some(secret_value)) => Ok(
+            MessageLocation::Chunk(crypto::derive_chunk_type(secret_value, &secret.label)),
+        ),
+        (Some(args::MessageChunkType::Derived) | None, None) => Err(Error::SecretRequired),
+    }
+}
+
+/// Returns the first textual chunk of the given type with the given keyword, if any. Textual
+/// chunks which cannot be decoded are skipped.
+fn find_text(png: &Png, chunk_type: &str, keyword: &str) -> Option<TextualData> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        .filter_map(|chunk| TextualData::from_chunk(chunk)?.ok())
+        .find(|textual_data| textual_data.keyword == keyword)
+}
+
+pub(crate) fn encode(opts: &args::Encode) -> Result<(), Error> {
+    let files = batch::collect_files([&opts.in_file], opts.batch.recursive)?;
+    batch::check_out_file(&files, opts.out_file.as_deref())?;
+
+    let location = message_location(
+        Some(&opts.chunk_type),
+        &opts.secret,
+        opts.keyword.as_deref(),
+    )?;
+    if matches!(location, MessageLocation::Camouflaged { .. }) && opts.compress {
+        return Err(Error::IncompatibleOptions(
+            "camouflaged messages can't be compressed".to_string(),
+        ));
+    }
+    // The passphrase may come from the environment, which the argument parser can't tell apart
+    if (opts.raw || matches!(location, MessageLocation::Camouflaged { .. }))
+        && opts.passphrase.is_some()
+    {
+        return Err(Error::IncompatibleOptions(
+            "raw and camouflaged messages can't be encrypted, unset PNGME_PASSPHRASE if it is set"
+                .to_string(),
+        ));
     }
 
-    let mut in_file = File::open(in_file)?;
-    let mut png_bytes = Vec::new();
-    in_file.read_to_end(&mut png_bytes)?;
-    let png = Png::try_from(png_bytes.as_slice())?;
+    batch::run(&files, &opts.batch, |in_file| {
+        encode_file(opts, &location, in_file)
+    })
+}
+
+fn encode_file(
+    opts: &args::Encode,
+    location: &MessageLocation,
+    in_file: &Path,
+) -> Result<(), Error> {
+    let out_file = batch::out_file(in_file, opts.out_file.as_deref());
+    let message = &opts.message;
+
+    let mut png = read_png(in_file)?;
+    let original = (!opts.no_verify).then(|| pixel_chunks(&png));
+
+    match location {
+        MessageLocation::Chunk(chunk_type) => {
+            // Allow only safe-to-modify chunks
+            if !chunk_type.is_modifiable() {
+                return Err(Error::UnmodifiableChunkType(chunk_type.clone()));
+            }
+
+            // Disallow duplicate chunk to be added
+            if png.chunk_by_type(&chunk_type.to_string()).is_some() {
+                return Err(Error::ChunkTypeExists(chunk_type.clone()));
+            }
+
+            let data = if opts.raw {
+                message.as_bytes().to_vec()
+            } else {
+                let envelope = Envelope {
+                    compressed: opts.compress,
+                    encrypted: opts.passphrase.is_some(),
+                    ..Envelope::text(message)
+                };
+                envelope.to_bytes(opts.passphrase.as_deref())?
+            };
+            png.append_chunk(Chunk::new(chunk_type.clone(), data))?;
+        }
+        MessageLocation::Camouflaged {
+            camouflage: args::Camouflage::CompressedText,
+            keyword,
+        } => {
+            // Disallow duplicate keyword to be added, so that the message can be found again
+            if find_text(&png, "zTXt", keyword).is_some() {
+                return Err(Error::KeywordExists(keyword.clone()));
+            }
+
+            png.append_chunk(TextualData::compressed(keyword, message).to_chunk()?)?;
+        }
+        MessageLocation::Camouflaged {
+            camouflage: args::Camouflage::Xmp,
+            keyword,
+        } => {
+            // Add the message to the existing XMP packet, if any, as there should only be one
+            let existing = png.chunks().iter().enumerate().find_map(|(index, chunk)| {
+                let textual_data = TextualData::from_chunk(chunk)?.ok()?;
+                (textual_data.chunk_type() == "iTXt" && &textual_data.keyword == keyword)
+                    .then_some((index, textual_data))
+            });
 
-    let chunk = png.chunk_by_type(&chunk_type.to_string());
-    match chunk {
-        Some(chunk) => {
-            println!("{}", chunk.data_as_string()?);
+            if let Some((index, mut textual_data)) = existing {
+                textual_data.text = text::xmp_with_description(&textual_data.text, message)?;
+                png.replace_chunk_at(index, textual_data.to_chunk()?);
+            } else {
+                let textual_data = TextualData::international(keyword, &text::xmp_packet(message));
+                png.append_chunk(textual_data.to_chunk()?)?;
+            }
         }
-        None => return Err(Error::ChunkTypeNotFound(chunk_type)),
     }
 
-    Ok(())
+    if let Some(original) = original {
+        verify_pixels(original, &png, in_file)?;
+    }
+    write_png(&out_file, &png.as_bytes())
+}
+
+pub(crate) fn decode(opts: &args::Decode) -> Result<(), Error> {
+    let files = batch::collect_files([&opts.in_file], opts.batch.recursive)?;
+    let batched = files.len() > 1;
+    let location = message_location(
+        opts.chunk_type.as_ref(),
+        &opts.secret,
+        opts.keyword.as_deref(),
+    )?;
+
+    batch::run(&files, &opts.batch, |in_file| {
+        decode_file(opts, &location, in_file, batched)
+    })
 }
 
-pub(crate) fn remove(opts: args::Remove) -> Result<(), Error> {
-    use std::io::{Read, Write};
+fn decode_file(
+    opts: &args::Decode,
+    location: &MessageLocation,
+    in_file: &Path,
+    batched: bool,
+) -> Result<(), Error> {
+    use std::io::Write;
+
+    let png = read_png(in_file)?;
+
+    let message = match location {
+        MessageLocation::Chunk(chunk_type) => {
+            // Allow only safe-to-modify chunks
+            if !chunk_type.is_modifiable() {
+                return Err(Error::UnmodifiableChunkType(chunk_type.clone()));
+            }
+
+            let chunk = png
+                .chunk_by_type(&chunk_type.to_string())
+                .ok_or_else(|| Error::ChunkTypeNotFound(chunk_type.clone()))?;
 
-    let out_file = opts.in_file.clone();
-    let in_file = opts.in_file;
-    let chunk_type = opts.chunk_type;
+            if Envelope::is_envelope(chunk.data()) {
+                match Envelope::from_bytes(chunk.data(), opts.passphrase.as_deref()) {
+                    Ok(envelope) if envelope.content_type == ContentType::Binary => {
+                        let mut stdout = std::io::stdout().lock();
+                        stdout.write_all(&envelope.message)?;
+                        stdout.flush()?;
+                        return Ok(());
+                    }
+                    Ok(envelope) => String::from_utf8(envelope.message)?,
+                    // Messages stored as is may start like an envelope by chance
+                    Err(error @ Error::InvalidEnvelope(_)) => {
+                        chunk.data_as_string().map_err(|_| error)?
+                    }
+                    Err(error) => return Err(error),
+                }
+            } else {
+                // Messages written before envelopes were introduced are stored as is
+                chunk.data_as_string()?
+            }
+        }
+        MessageLocation::Camouflaged {
+            camouflage: args::Camouflage::CompressedText,
+            keyword,
+        } => match find_text(&png, "zTXt", keyword) {
+            Some(textual_data) => textual_data.text,
+            None => return Err(Error::KeywordNotFound(keyword.clone())),
+        },
+        MessageLocation::Camouflaged {
+            camouflage: args::Camouflage::Xmp,
+            keyword,
+        } => match find_text(&png, "iTXt", keyword)
+            .and_then(|textual_data| text::xmp_description(&textual_data.text))
+        {
+            Some(description) => description,
+            None => return Err(Error::KeywordNotFound(keyword.clone())),
+        },
+    };
+
+    if batched {
+        println!("{}: {}", in_file.display(), message);
+    } else {
+        println!("{message}");
+    }
+
+    Ok(())
+}
 
+pub(crate) fn remove(opts: &args::Remove) -> Result<(), Error> {
     // Allow only safe-to-modify chunks
-    if !chunk_type.is_modifiable() {
-        return Err(Error::UnmodifiableChunkType(chunk_type));
+    if let Some(chunk_type) = opts
+        .chunk_types
+        .iter()
+        .find(|chunk_type| !chunk_type.is_modifiable())
+    {
+        return Err(Error::UnmodifiableChunkType(chunk_type.clone()));
+    }
+
+    let files = batch::collect_files([&opts.in_file], opts.batch.recursive)?;
+    batch::check_out_file(&files, opts.out_file.as_deref())?;
+
+    batch::run(&files, &opts.batch, |in_file| remove_file(opts, in_file))
+}
+
+fn remove_file(opts: &args::Remove, in_file: &Path) -> Result<(), Error> {
+    let out_file = batch::out_file(in_file, opts.out_file.as_deref());
+
+    let mut png = read_png(in_file)?;
+    let original = (!opts.no_verify).then(|| pixel_chunks(&png));
+
+    let removed_chunks = png.remove_chunks(|chunk| {
+        if opts.all_private {
+            chunk.chunk_type().is_modifiable()
+        } else {
+            opts.chunk_types.contains(chunk.chunk_type())
+        }
+    });
+
+    // Remove the chunk types which were found and report the others, unless none was found
+    let missing: Vec<&ChunkType> = opts
+        .chunk_types
+        .iter()
+        .filter(|&chunk_type| {
+            !removed_chunks
+                .iter()
+                .any(|chunk| chunk.chunk_type() == chunk_type)
+        })
+        .collect();
+    if removed_chunks.is_empty() {
+        if let Some(&chunk_type) = missing.first() {
+            return Err(Error::ChunkTypeNotFound(chunk_type.clone()));
+        }
+    }
+
+    if let Some(original) = original {
+        verify_pixels(original, &png, in_file)?;
     }
+    write_png(&out_file, &png.as_bytes())?;
+
+    let mut output = status_output(&out_file);
 
-    let mut in_file = File::open(in_file)?;
-    let mut png_bytes = Vec::new();
-    in_file.read_to_end(&mut png_bytes)?;
-    let mut png = Png::try_from(png_bytes.as_slice())?;
+    if removed_chunks.is_empty() {
+        writeln!(
+            output,
+            "No chunks found in file '{}' which could possibly contain messages",
+            in_file.display()
+        )?;
+        return Ok(());
+    }
 
-    png.remove_chunk(&chunk_type.to_string())?;
+    writeln!(
+        output,
+        "PNG chunks removed from file '{}':\n",
+        in_file.display()
+    )?;
+    for chunk in removed_chunks {
+        writeln!(output, "{}", chunk.chunk_type())?;
+    }
 
-    let mut out_file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(out_file)?;
-    out_file.write_all(&png.as_bytes())?;
+    if !missing.is_empty() {
+        writeln!(
+            output,
+            "\nPNG chunks not found in file '{}':\n",
+            in_file.display()
+        )?;
+        for chunk_type in missing {
+            writeln!(output, "{chunk_type}")?;
+        }
+    }
 
     Ok(())
 }
 
-pub(crate) fn print(opts: args::Print) -> Result<(), Error> {
-    use std::io::Read;
+pub(crate) fn print(opts: &args::Print) -> Result<(), Error> {
+    let files = batch::collect_files(&opts.in_files, opts.batch.recursive)?;
+
+    batch::run(&files, &opts.batch, print_file)
+}
 
-    let in_file = opts.in_file;
-    let input_file = in_file.clone();
+fn print_file(in_file: &Path) -> Result<(), Error> {
+    let png = read_png(in_file)?;
 
-    let mut in_file = File::open(in_file)?;
-    let mut png_bytes = Vec::new();
-    in_file.read_to_end(&mut png_bytes)?;
-    let png = Png::try_from(png_bytes.as_slice())?;
+    // Hold the lock so that output for different files is not interleaved
+    let _stdout = std::io::stdout().lock();
 
     // Print only safe-to-modify chunks
     if !png
@@ -131,11 +601,14 @@ pub(crate) fn print(opts: args::Print) -> Result<(), Error> {
         .iter()
         .any(|chunk| chunk.chunk_type().is_modifiable())
     {
-        println!("No chunks found which could possibly contain messages");
+        println!(
+            "No chunks found in file '{}' which could possibly contain messages",
+            in_file.display()
+        );
         return Ok(());
     }
 
-    println!("PNG chunks found in file '{}':\n", input_file.display());
+    println!("PNG chunks found in file '{}':\n", in_file.display());
     for chunk in png.chunks() {
         if chunk.chunk_type().is_modifiable() {
             println!("{}", chunk.chunk_type());
@@ -144,3 +617,1866 @@ pub(crate) fn print(opts: args::Print) -> Result<(), Error> {
 
     Ok(())
 }
+
+pub(crate) fn strip(opts: &args::Strip) -> Result<(), Error> {
+    // Critical chunks are required to display the image
+    if let Some(chunk_type) = opts
+        .remove
+        .iter()
+        .find(|chunk_type| chunk_type.is_critical())
+    {
+        return Err(Error::CriticalChunkType(chunk_type.clone()));
+    }
+
+    let files = batch::collect_files(&opts.in_files, opts.batch.recursive)?;
+    batch::check_out_file(&files, opts.out_file.as_deref())?;
+
+    batch::run(&files, &opts.batch, |in_file| strip_file(opts, in_file))
+}
+
+fn strip_file(opts: &args::Strip, in_file: &Path) -> Result<(), Error> {
+    let out_file = batch::out_file(in_file, opts.out_file.as_deref());
+
+    let mut png = read_png(in_file)?;
+    let original_length = png.as_bytes().len();
+
+    let chunk_types: Vec<ChunkType> = png
+        .chunks()
+        .iter()
+        .map(Chunk::chunk_type)
+        .filter(|&chunk_type| {
+            opts.remove.contains(chunk_type)
+                || (!opts.keep.contains(chunk_type) && !opts.preset.keeps(chunk_type))
+        })
+        .cloned()
+        .collect();
+
+    let mut removed_chunks = Vec::new();
+    for chunk_type in &chunk_types {
+        removed_chunks.push(png.remove_chunk(&chunk_type.to_string())?);
+    }
+
+    let trailing_data = if opts.keep_trailing_data {
+        Vec::new()
+    } else {
+        png.remove_trailing_data()
+    };
+
+    let png_bytes = png.as_bytes();
+    write_png(&out_file, &png_bytes)?;
+
+    let mut output = status_output(&out_file);
+
+    if removed_chunks.is_empty() && trailing_data.is_empty() {
+        writeln!(output, "Nothing to strip from file '{}'", in_file.display())?;
+        return Ok(());
+    }
+
+    writeln!(output, "Stripped from file '{}':\n", in_file.display())?;
+    for chunk in &removed_chunks {
+        // 12 bytes for the chunk length, chunk type and CRC
+        writeln!(
+            output,
+            "{} ({} bytes)",
+            chunk.chunk_type(),
+            chunk.length() + 12
+        )?;
+    }
+    if !trailing_data.is_empty() {
+        writeln!(output, "Data after IEND ({} bytes)", trailing_data.len())?;
+    }
+
+    writeln!(
+        output,
+        "\nRemoved {} bytes ({} -> {} bytes)",
+        original_length - png_bytes.len(),
+        original_length,
+        png_bytes.len()
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn scan(opts: &args::Scan) -> Result<(), Error> {
+    let files = batch::collect_files(&opts.in_files, opts.batch.recursive)?;
+
+    batch::run(&files, &opts.batch, |in_file| scan_file(opts, in_file))
+}
+
+fn scan_file(opts: &args::Scan, in_file: &Path) -> Result<(), Error> {
+    let png = read_png(in_file)?;
+    let matches = scan_chunks(opts, &png);
+
+    if matches.is_empty() {
+        log::debug!("No matching chunks found in file '{}'", in_file.display());
+        return Ok(());
+    }
+
+    // Hold the lock so that output for different files is not interleaved
+    let _stdout = std::io::stdout().lock();
+
+    println!("{}", in_file.display());
+    for (chunk, found) in matches {
+        match found {
+            Some(found) => println!(
+                "    {} ({} bytes): {:?}",
+                chunk.chunk_type(),
+                chunk.length(),
+                found
+            ),
+            None => println!("    {} ({} bytes)", chunk.chunk_type(), chunk.length()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the chunks of the PNG which `scan` lists, along with the text matching the regular
+/// expression if one is given. The regular expression is matched against the decoded text of
+/// textual chunks and messages, and against the raw data of other chunks.
+fn scan_chunks<'a>(opts: &args::Scan, png: &'a Png) -> Vec<(&'a Chunk, Option<String>)> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| match &opts.type_pattern {
+            Some(type_pattern) => type_pattern.is_match(&chunk.chunk_type().to_string()),
+            None => chunk.chunk_type().is_modifiable(),
+        })
+        .filter_map(|chunk| match &opts.regex {
+            Some(regex) => {
+                let text = chunk_text(chunk)
+                    .unwrap_or_else(|| String::from_utf8_lossy(chunk.data()).into_owned());
+                regex
+                    .find(&text)
+                    .map(|found| (chunk, Some(found.as_str().to_string())))
+            }
+            None => Some((chunk, None)),
+        })
+        .collect()
+}
+
+pub(crate) fn carve(opts: &args::Carve) -> Result<(), Error> {
+    let mut found_any = false;
+    let save = |carved: carve::CarvedPng| {
+        let carve::CarvedPng {
+            offset,
+            length,
+            png,
+        } = carved;
+        if !found_any {
+            std::fs::create_dir_all(&opts.out_dir)
+                .map_err(|error| Error::from(error).in_file(&opts.out_dir))?;
+            println!("PNG files found in file '{}':", opts.in_file.display());
+            found_any = true;
+        }
+
+        let out_file = opts.out_dir.join(carved_file_name(&opts.in_file, offset));
+        write_png(&out_file, &png.as_bytes())?;
+
+        println!(
+            "\nOffset {offset:#010x} ({length} bytes), saved as '{}'",
+            out_file.display()
+        );
+        for chunk in png.chunks() {
+            if chunk.chunk_type().is_modifiable() {
+                let text = chunk_text(chunk)
+                    .unwrap_or_else(|| String::from_utf8_lossy(chunk.data()).into_owned());
+                println!("    {}: {text}", chunk.chunk_type());
+            }
+        }
+
+        Ok(())
+    };
+
+    // Scan the input as it is read, since it may be a disk image much larger than memory
+    let count = if batch::is_stdio(&opts.in_file) {
+        carve::carve(std::io::stdin().lock(), save)
+    } else {
+        File::open(&opts.in_file)
+            .map_err(Error::from)
+            .and_then(|file| carve::carve(std::io::BufReader::new(file), save))
+    }
+    .map_err(|error| error.in_file(&opts.in_file))?;
+
+    if count == 0 {
+        println!("No PNG files found in file '{}'", opts.in_file.display());
+    }
+
+    Ok(())
+}
+
+/// Returns the name to save the PNG file found at the given offset of `in_file` as.
+fn carved_file_name(in_file: &Path, offset: usize) -> String {
+    let file_stem = if batch::is_stdio(in_file) {
+        batch::STDIN_FILE_STEM.into()
+    } else {
+        in_file
+            .file_stem()
+            .map_or_else(|| "carved".into(), |file_stem| file_stem.to_string_lossy())
+    };
+
+    format!("{file_stem}-{offset:#010x}.png")
+}
+
+/// Decodes a message stored in a chunk: in an envelope, or as is, compressed or encrypted like
+/// older versions of pngme and `import` store messages. Returns the message and whether it was
+/// compressed and encrypted. Fails only if the message is encrypted and can't be decrypted.
+fn decode_payload(data: &[u8], passphrase: Option<&str>) -> Result<(Vec<u8>, bool, bool), Error> {
+    if Envelope::is_envelope(data) {
+        match Envelope::from_bytes(data, passphrase) {
+            Ok(envelope) => return Ok((envelope.message, envelope.compressed, envelope.encrypted)),
+            // Messages stored as is may start like an envelope by chance
+            Err(Error::InvalidEnvelope(_)) => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    let (data, encrypted) = if crypto::is_encrypted(data) {
+        let passphrase = passphrase.ok_or(Error::PassphraseRequired)?;
+        (crypto::decrypt(data, passphrase)?, true)
+    } else {
+        (data.to_vec(), false)
+    };
+
+    Ok(match zlib::decompress(&data, zlib::MAX_LENGTH) {
+        Ok(data) => (data, true, encrypted),
+        Err(_) => (data, false, encrypted),
+    })
+}
+
+pub(crate) fn extract(opts: &args::Extract) -> Result<(), Error> {
+    let png = read_png(&opts.in_file)?;
+
+    std::fs::create_dir_all(&opts.out)?;
+
+    let mut manifest = ExtractManifest {
+        file: opts.in_file.display().to_string(),
+        chunks: Vec::new(),
+    };
+
+    // The chunks start after the 8 byte PNG header
+    let mut offset = 8;
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_offset = offset;
+        // 12 bytes for the chunk length, chunk type and CRC
+        offset += chunk.length() as usize + 12;
+
+        let textual_data = match TextualData::from_chunk(chunk) {
+            Some(Ok(textual_data)) => Some(textual_data),
+            Some(Err(error)) => {
+                log::warn!("Extracting raw data of chunk {index}: {error}");
+                None
+            }
+            None if chunk.chunk_type().is_modifiable() => None,
+            None => continue,
+        };
+
+        let (data, compressed, encrypted) = match &textual_data {
+            Some(textual_data) => (
+                textual_data.text.as_bytes().to_vec(),
+                textual_data.compressed,
+                false,
+            ),
+            None => {
+                decode_payload(chunk.data(), opts.passphrase.as_deref()).unwrap_or_else(|error| {
+                    log::warn!("Extracting encrypted data of chunk {index}: {error}");
+                    (chunk.data().to_vec(), false, true)
+                })
+            }
+        };
+
+        let encoding = if std::str::from_utf8(&data).is_ok() {
+            PayloadEncoding::Utf8
+        } else {
+            PayloadEncoding::Binary
+        };
+        let extension = match encoding {
+            PayloadEncoding::Utf8 => "txt",
+            PayloadEncoding::Binary => "bin",
+        };
+        let path = format!("{index:03}-{}.{extension}", chunk.chunk_type());
+        std::fs::write(opts.out.join(&path), &data)?;
+
+        let (keyword, language_tag, translated_keyword) = match textual_data {
+            Some(textual_data) => (
+                Some(textual_data.keyword),
+                textual_data.language_tag,
+                textual_data.translated_keyword,
+            ),
+            None => (None, None, None),
+        };
+
+        manifest.chunks.push(ExtractedChunk {
+            chunk_type: chunk.chunk_type().to_string(),
+            index,
+            offset: chunk_offset,
+            length: chunk.length(),
+            crc: chunk.crc(),
+            payload: Payload {
+                path,
+                length: data.len(),
+                encoding,
+                compressed,
+                encrypted,
+                keyword,
+                language_tag,
+                translated_keyword,
+            },
+        });
+    }
+
+    if manifest.chunks.is_empty() {
+        println!(
+            "No chunks found in file '{}' which could possibly contain messages",
+            opts.in_file.display()
+        );
+        return Ok(());
+    }
+
+    let (manifest_file, manifest_contents) = match opts.format {
+        args::ManifestFormat::Json => ("manifest.json", serde_json::to_string_pretty(&manifest)?),
+        args::ManifestFormat::Toml => ("manifest.toml", toml::to_string_pretty(&manifest)?),
+    };
+    std::fs::write(opts.out.join(manifest_file), manifest_contents)?;
+
+    println!(
+        "Messages extracted from file '{}' to '{}':\n",
+        opts.in_file.display(),
+        opts.out.display()
+    );
+    for chunk in &manifest.chunks {
+        println!("{} -> {}", chunk.chunk_type, chunk.payload.path);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn import(opts: &args::Import) -> Result<(), Error> {
+    use std::str::FromStr;
+
+    let out_file = batch::out_file(&opts.in_file, opts.out_file.as_deref());
+    let manifest_dir = opts.manifest.parent().unwrap_or_else(|| Path::new(""));
+    let manifest: ImportManifest = toml::from_str(&std::fs::read_to_string(&opts.manifest)?)?;
+
+    let mut png = read_png(&opts.in_file)?;
+
+    // Validate every entry and prepare its data before modifying the PNG
+    let mut chunks = Vec::new();
+    for (index, entry) in manifest.chunks.iter().enumerate() {
+        let invalid = |message: String| Error::InvalidManifestEntry(index, message);
+
+        let chunk_type = ChunkType::from_str(&entry.chunk_type)?;
+        if !chunk_type.is_modifiable() {
+            return Err(Error::UnmodifiableChunkType(chunk_type));
+        }
+        if png.chunk_by_type(&entry.chunk_type).is_some()
+            || chunks
+                .iter()
+                .any(|(_, chunk): &(_, Chunk)| chunk.chunk_type() == &chunk_type)
+        {
+            return Err(Error::ChunkTypeExists(chunk_type));
+        }
+
+        let mut data = match (&entry.text, &entry.file, &entry.env) {
+            (Some(text), None, None) => text.as_bytes().to_vec(),
+            (None, Some(file), None) => std::fs::read(manifest_dir.join(file))?,
+            (None, None, Some(env)) => std::env::var(env)
+                .map_err(|error| invalid(format!("environment variable '{env}': {error}")))?
+                .into_bytes(),
+            _ => {
+                return Err(invalid(
+                    "expected exactly one of 'text', 'file' and 'env'".to_string(),
+                ))
+            }
+        };
+
+        if entry.compress {
+            data = zlib::compress(&data, 9);
+        }
+        if entry.encrypt {
+            let passphrase = opts
+                .passphrase
+                .as_deref()
+                .ok_or(Error::PassphraseRequired)?;
+            data = crypto::encrypt(&data, passphrase);
+        }
+
+        chunks.push((entry.placement, Chunk::new(chunk_type, data)));
+    }
+
+    for (placement, chunk) in chunks {
+        let position = |chunk_type: &str| {
+            png.chunks()
+                .iter()
+                .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        };
+        let index = match placement {
+            Placement::Start => position("IHDR").map(|index| index + 1),
+            Placement::BeforeData => position("IDAT"),
+            Placement::AfterData => png
+                .chunks()
+                .iter()
+                .rposition(|chunk| chunk.chunk_type().to_string() == "IDAT")
+                .map(|index| index + 1),
+            Placement::End => position("IEND"),
+        };
+
+        match index {
+            Some(index) => png.insert_chunk(index, chunk),
+            None => png.append_chunk(chunk)?,
+        }
+    }
+
+    write_png_atomically(&out_file, &png.as_bytes())?;
+
+    let mut output = status_output(&out_file);
+    writeln!(
+        output,
+        "Imported {} chunks into file '{}'",
+        manifest.chunks.len(),
+        out_file.display()
+    )?;
+
+    Ok(())
+}
+
+/// Returns the text stored in a chunk, if it is a textual chunk or contains a UTF-8 message which
+/// [`decode_payload`](decode_payload) can decode without a passphrase.
+fn chunk_text(chunk: &Chunk) -> Option<String> {
+    match TextualData::from_chunk(chunk) {
+        Some(textual_data) => textual_data.ok().map(|textual_data| textual_data.text),
+        None => decode_payload(chunk.data(), None)
+            .ok()
+            .and_then(|(data, ..)| String::from_utf8(data).ok()),
+    }
+}
+
+pub(crate) fn diff(opts: &args::Diff) -> Result<(), Error> {
+    let a = read_png(&opts.a)?;
+    let b = read_png(&opts.b)?;
+
+    println!("--- {}", opts.a.display());
+    println!("+++ {}", opts.b.display());
+
+    let changes = diff::diff_chunks(a.chunks(), b.chunks());
+    if changes.is_empty() {
+        println!("\nChunks: identical");
+    } else {
+        println!("\nChunks:");
+    }
+
+    for change in changes {
+        match change {
+            ChunkChange::Removed(i) => {
+                let chunk = &a.chunks()[i];
+                println!(
+                    "    - {} [{i}] ({} bytes)",
+                    chunk.chunk_type(),
+                    chunk.length()
+                );
+            }
+            ChunkChange::Added(j) => {
+                let chunk = &b.chunks()[j];
+                println!(
+                    "    + {} [{j}] ({} bytes)",
+                    chunk.chunk_type(),
+                    chunk.length()
+                );
+            }
+            ChunkChange::Reordered(i, j) => {
+                println!("    > {} [{i} -> {j}]", a.chunks()[i].chunk_type());
+            }
+            ChunkChange::Modified(i, j) => {
+                let (a_chunk, b_chunk) = (&a.chunks()[i], &b.chunks()[j]);
+                println!(
+                    "    ~ {} [{i} -> {j}] ({} -> {} bytes, CRC {:08x} -> {:08x})",
+                    a_chunk.chunk_type(),
+                    a_chunk.length(),
+                    b_chunk.length(),
+                    a_chunk.crc(),
+                    b_chunk.crc()
+                );
+
+                if let (Some(a_text), Some(b_text)) = (chunk_text(a_chunk), chunk_text(b_chunk)) {
+                    for line in diff::diff_lines(&a_text, &b_text) {
+                        match line {
+                            LineChange::Unchanged(line) => println!("          {line}"),
+                            LineChange::Removed(line) => println!("        - {line}"),
+                            LineChange::Added(line) => println!("        + {line}"),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let describe = |identical: bool| if identical { "identical" } else { "different" };
+
+    println!(
+        "\nData after IEND: {} ({} -> {} bytes)",
+        describe(a.trailing_data() == b.trailing_data()),
+        a.trailing_data().len(),
+        b.trailing_data().len()
+    );
+
+    match (
+        image::decompress_image_data(&a),
+        image::decompress_image_data(&b),
+    ) {
+        (Ok(a_image_data), Ok(b_image_data)) => {
+            println!(
+                "Decompressed image data: {}",
+                describe(a_image_data == b_image_data)
+            );
+        }
+        (Err(error), _) | (_, Err(error)) => {
+            println!("Decompressed image data: unknown ({error})");
+        }
+    }
+
+    match (Image::decode(&a), Image::decode(&b)) {
+        (Ok(a_image), Ok(b_image)) => println!("Pixels: {}", describe_pixels(&a_image, &b_image)),
+        (Err(error), _) | (_, Err(error)) => println!("Pixels: unknown ({error})"),
+    }
+
+    Ok(())
+}
+
+/// Describes how the pixels of two images differ, comparing their colours rather than the way
+/// they are stored.
+fn describe_pixels(a: &Image, b: &Image) -> String {
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        return format!(
+            "different dimensions ({}x{} -> {}x{})",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        );
+    }
+
+    let total = a.width() * a.height();
+    let changed = (0..a.height())
+        .flat_map(|y| (0..a.width()).map(move |x| (x, y)))
+        .filter(|&(x, y)| a.rgba(x, y) != b.rgba(x, y))
+        .count();
+
+    if changed == 0 {
+        "identical".to_string()
+    } else {
+        format!("{changed} of {total} pixels different")
+    }
+}
+
+pub(crate) fn compare_pixels(opts: &args::ComparePixels) -> Result<(), Error> {
+    let a = Image::decode(&read_png(&opts.a)?).map_err(|error| error.in_file(&opts.a))?;
+    let b = Image::decode(&read_png(&opts.b)?).map_err(|error| error.in_file(&opts.b))?;
+    let comparison = compare::compare(&a, &b)?;
+
+    if comparison.is_identical() {
+        println!("Pixels: identical ({} pixels)", comparison.pixels);
+        return Ok(());
+    }
+
+    println!(
+        "Pixels: {} of {} different",
+        comparison.changed_pixels, comparison.pixels
+    );
+    println!(
+        "Samples: {} of {} different ({}-bit)",
+        comparison.changed_samples, comparison.samples, comparison.bit_depth
+    );
+    println!("Maximum difference: {}", comparison.max_delta);
+    println!("PSNR: {:.2} dB", comparison.psnr().unwrap_or(f64::INFINITY));
+
+    Err(Error::PixelsDiffer(format!(
+        "{} of {} pixels different",
+        comparison.changed_pixels, comparison.pixels
+    )))
+}
+
+pub(crate) fn textconv(opts: &args::Textconv) -> Result<(), Error> {
+    let in_file = match &opts.in_file {
+        Some(in_file) if !opts.install => in_file,
+        _ => return install_textconv(opts.global),
+    };
+
+    let png = read_png(in_file)?;
+
+    // Build the whole description first so that git never sees partial output
+    let description = describe_png(&png).expect("Writing to a string should not fail");
+
+    print!("{description}");
+
+    Ok(())
+}
+
+/// Describes the chunks of a PNG for `textconv`, one line per chunk followed by the chunk's text.
+fn describe_png(png: &Png) -> Result<String, std::fmt::Error> {
+    use std::fmt::Write;
+
+    let mut description = String::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type().to_string();
+        writeln!(
+            description,
+            "chunk {index} {chunk_type} length={} crc={:08x}",
+            chunk.length(),
+            chunk.crc()
+        )?;
+
+        if chunk_type == "IHDR" && chunk.length() == 13 {
+            let data = chunk.data();
+            writeln!(
+                description,
+                "    width={} height={} bit-depth={} colour-type={} interlace={}",
+                u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+                data[8],
+                data[9],
+                data[12]
+            )?;
+            continue;
+        }
+
+        let text = match TextualData::from_chunk(chunk) {
+            Some(Ok(textual_data)) => {
+                writeln!(description, "    keyword={}", textual_data.keyword)?;
+                Some(textual_data.text.into_bytes())
+            }
+            Some(Err(error)) => {
+                writeln!(description, "    <{error}>")?;
+                None
+            }
+            None if chunk.chunk_type().is_modifiable() => {
+                if let Ok((data, ..)) = decode_payload(chunk.data(), None) {
+                    Some(data)
+                } else {
+                    writeln!(description, "    <encrypted>")?;
+                    None
+                }
+            }
+            None => None,
+        };
+
+        match text.map(String::from_utf8) {
+            Some(Ok(text)) => {
+                for line in text.lines() {
+                    writeln!(description, "    | {line}")?;
+                }
+            }
+            Some(Err(error)) => {
+                writeln!(
+                    description,
+                    "    <binary data, {} bytes>",
+                    error.as_bytes().len()
+                )?;
+            }
+            None => {}
+        }
+    }
+
+    if !png.trailing_data().is_empty() {
+        writeln!(
+            description,
+            "data after IEND length={} crc={:08x}",
+            png.trailing_data().len(),
+            crate::chunk::CRC_32.checksum(png.trailing_data())
+        )?;
+    }
+
+    Ok(description)
+}
+
+/// Configures git to use `pngme textconv` for files marked with `diff=pngme` in `.gitattributes`.
+fn install_textconv(global: bool) -> Result<(), Error> {
+    for (key, value) in [
+        ("diff.pngme.textconv", "pngme textconv"),
+        ("diff.pngme.cachetextconv", "true"),
+    ] {
+        let mut command = std::process::Command::new("git");
+        command.arg("config");
+        if global {
+            command.arg("--global");
+        }
+
+        let status = command.args([key, value]).status()?;
+        if !status.success() {
+            return Err(Error::GitFailed(status));
+        }
+    }
+
+    println!("Configured git to diff PNG files using 'pngme textconv'.");
+    println!("Add the following line to a .gitattributes file to enable it for PNG files:\n");
+    println!("*.png diff=pngme");
+
+    Ok(())
+}
+
+pub(crate) fn chunk_type(opts: &args::ChunkType) -> Result<(), Error> {
+    match &opts.subcommand {
+        args::ChunkTypeCommand::Suggest(opts) => suggest_chunk_type(opts),
+        args::ChunkTypeCommand::Explain(opts) => {
+            explain_chunk_type(&opts.chunk_type);
+            Ok(())
+        }
+    }
+}
+
+fn suggest_chunk_type(opts: &args::SuggestChunkType) -> Result<(), Error> {
+    if let Some(seed) = &opts.seed {
+        let chunk_type =
+            ChunkType::from_seed(seed).ok_or_else(|| Error::InvalidChunkType(seed.clone()))?;
+        println!("{chunk_type}");
+    } else {
+        for _ in 0..opts.count {
+            println!("{}", ChunkType::random());
+        }
+    }
+
+    Ok(())
+}
+
+fn explain_chunk_type(chunk_type: &ChunkType) {
+    let case = |byte: u8| {
+        if byte.is_ascii_uppercase() {
+            "uppercase"
+        } else {
+            "lowercase"
+        }
+    };
+    let [ancillary, private, reserved, safe_to_copy] = chunk_type.bytes();
+
+    println!("{chunk_type}");
+    println!(
+        "  {}  {}  {}",
+        char::from(ancillary),
+        case(ancillary),
+        if chunk_type.is_critical() {
+            "critical: required to display the image"
+        } else {
+            "ancillary: not required to display the image"
+        }
+    );
+    println!(
+        "  {}  {}  {}",
+        char::from(private),
+        case(private),
+        if chunk_type.is_public() {
+            "public: defined by the PNG specification"
+        } else {
+            "private: not defined by the PNG specification"
+        }
+    );
+    println!(
+        "  {}  {}  {}",
+        char::from(reserved),
+        case(reserved),
+        if chunk_type.is_reserved_bit_valid() {
+            "reserved: valid"
+        } else {
+            "reserved: invalid, must be uppercase"
+        }
+    );
+    println!(
+        "  {}  {}  {}",
+        char::from(safe_to_copy),
+        case(safe_to_copy),
+        if chunk_type.is_safe_to_copy() {
+            "safe-to-copy: doesn't depend on the image data"
+        } else {
+            "unsafe-to-copy: depends on the image data"
+        }
+    );
+
+    if chunk_type.is_modifiable() {
+        println!("Can be used for messages: yes");
+    } else {
+        println!(
+            "Can be used for messages: no, try '{}' instead",
+            chunk_type.to_modifiable()
+        );
+    }
+}
+
+pub(crate) fn reencode(opts: &args::Reencode) -> Result<(), Error> {
+    let files = batch::collect_files([&opts.in_file], opts.batch.recursive)?;
+    batch::check_out_file(&files, opts.out_file.as_deref())?;
+
+    batch::run(&files, &opts.batch, |in_file| reencode_file(opts, in_file))
+}
+
+fn reencode_file(opts: &args::Reencode, in_file: &Path) -> Result<(), Error> {
+    use std::io::Write;
+
+    let out_file = batch::out_file(in_file, opts.out_file.as_deref());
+
+    let mut png = read_png(in_file)?;
+    let image = Image::decode(&png)?;
+    let original_length = png.image_data().len();
+
+    opts.encoding.write_image(&mut png, &image);
+    write_png(&out_file, &png.as_bytes())?;
+
+    writeln!(
+        status_output(&out_file),
+        "Re-encoded image data of file '{}': {} -> {} bytes",
+        in_file.display(),
+        original_length,
+        png.image_data().len()
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn hide(opts: &args::Hide) -> Result<(), Error> {
+    let passphrase = opts
+        .hiding
+        .passphrase
+        .as_deref()
+        .ok_or(Error::PassphraseRequired)?;
+    let files = batch::collect_files([&opts.in_file], opts.batch.recursive)?;
+    batch::check_out_file(&files, opts.out_file.as_deref())?;
+
+    batch::run(&files, &opts.batch, |in_file| {
+        hide_file(opts, passphrase, in_file)
+    })
+}
+
+fn hide_file(opts: &args::Hide, passphrase: &str, in_file: &Path) -> Result<(), Error> {
+    use std::io::Write;
+
+    let out_file = batch::out_file(in_file, opts.out_file.as_deref());
+
+    let mut png = read_png(in_file)?;
+    let original = Image::decode(&png)?;
+    let capacity = hide_message(
+        &mut png,
+        &original,
+        opts.message.as_bytes(),
+        passphrase,
+        &opts.hiding,
+        &opts.encoding,
+    )?;
+
+    let comparison = compare::compare(&original, &Image::decode(&png)?)?;
+    write_png(&out_file, &png.as_bytes())?;
+
+    let mut output = status_output(&out_file);
+    writeln!(
+        output,
+        "Hid {} of {} bytes in file '{}'",
+        opts.message.len(),
+        capacity,
+        out_file.display()
+    )?;
+    writeln!(output, "Pixels: {comparison}")?;
+
+    Ok(())
+}
+
+/// Hides the message in the PNG, whose image is `original`, and returns the capacity of the image
+/// for the hiding method.
+fn hide_message(
+    png: &mut Png,
+    original: &Image,
+    message: &[u8],
+    passphrase: &str,
+    hiding: &args::Hiding,
+    encoding: &args::Encoding,
+) -> Result<usize, Error> {
+    let selection = hiding.selection.selection();
+
+    match hiding.method {
+        args::HidingMethod::Lsb => {
+            let mut image = original.clone();
+            lsb::embed(&mut image, message, passphrase, selection)?;
+            encoding.write_image(png, &image);
+            lsb::capacity(&image, selection)
+        }
+        args::HidingMethod::Palette => {
+            let mut image = original.clone();
+            palette::embed(png, &mut image, message, passphrase)?;
+            encoding.write_image(png, &image);
+            palette::capacity(&image)
+        }
+        args::HidingMethod::Filter => {
+            let filter_types = filter::embed(original.header(), message, passphrase)?;
+            let image_data = original.encode_with_filters(&filter_types, encoding.level);
+            png.replace_image_data(&image_data, encoding.idat_size as usize);
+            Ok(filter::capacity(original.header()))
+        }
+        args::HidingMethod::Idat => {
+            // The compressed image data is kept as it is and only split differently
+            let idat_size = encoding.idat_size as usize;
+            idat::embed(png, message, passphrase, idat_size)?;
+            Ok(idat::capacity(png, idat_size))
+        }
+    }
+}
+
+pub(crate) fn reveal(opts: &args::Reveal) -> Result<(), Error> {
+    if opts.data_image {
+        return reveal_data_image(opts);
+    }
+
+    let passphrase = opts
+        .hiding
+        .passphrase
+        .as_deref()
+        .ok_or(Error::PassphraseRequired)?;
+    let files = batch::collect_files(&opts.in_files, opts.batch.recursive)?;
+    let batched = files.len() > 1;
+
+    batch::run(&files, &opts.batch, |in_file| {
+        let png = read_png(in_file)?;
+        let selection = opts.hiding.selection.selection();
+
+        let payload = match opts.hiding.method {
+            args::HidingMethod::Lsb => lsb::extract(&Image::decode(&png)?, passphrase, selection)?,
+            args::HidingMethod::Palette => palette::extract(&Image::decode(&png)?, passphrase)?,
+            args::HidingMethod::Filter => filter::extract(&png, passphrase)?,
+            args::HidingMethod::Idat => idat::extract(&png, passphrase)?,
+        };
+        // A wrong passphrase yields random bytes, which are unlikely to be valid UTF-8
+        let message = String::from_utf8(payload).map_err(|_| Error::PayloadNotFound)?;
+
+        if batched {
+            println!("{}: {}", in_file.display(), message);
+        } else {
+            println!("{message}");
+        }
+
+        Ok(())
+    })
+}
+
+fn reveal_data_image(opts: &args::Reveal) -> Result<(), Error> {
+    use std::io::Write;
+
+    let files = batch::collect_files(&opts.in_files, opts.batch.recursive)?;
+
+    batch::run(&files, &opts.batch, |in_file| {
+        let data = create::read_data_image(&Image::decode(&read_png(in_file)?)?)?;
+
+        // Hold the lock so that the data of different files is not interleaved
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&data)?;
+        stdout.flush()?;
+
+        Ok(())
+    })
+}
+
+pub(crate) fn capacity(opts: &args::Capacity) -> Result<(), Error> {
+    let files = batch::collect_files(&opts.in_files, opts.batch.recursive)?;
+    let selection = opts.selection.selection();
+
+    batch::run(&files, &opts.batch, |in_file| {
+        let png = read_png(in_file)?;
+        let image = Image::decode(&png)?;
+        let capacities = [
+            ("lsb", lsb::capacity(&image, selection)),
+            ("palette", palette::capacity(&image)),
+            ("filter", Ok(filter::capacity(image.header()))),
+            ("idat", Ok(idat::capacity(&png, opts.idat_size as usize))),
+        ];
+
+        // Hold the lock so that output for different files is not interleaved
+        let _stdout = std::io::stdout().lock();
+
+        println!("{}", in_file.display());
+        for (method, capacity) in capacities {
+            match capacity {
+                Ok(capacity) => println!("    {method}: {capacity} bytes"),
+                Err(error) => println!("    {method}: {error}"),
+            }
+        }
+
+        Ok(())
+    })
+}
+
+pub(crate) fn analyze(opts: &args::Analyze) -> Result<(), Error> {
+    let files = batch::collect_files(&opts.in_files, opts.batch.recursive)?;
+
+    batch::run(&files, &opts.batch, |in_file| {
+        let analysis = analyze::analyze(&read_png(in_file)?);
+        let score = analysis.score();
+        if score < opts.min_score {
+            log::debug!(
+                "Skipping file '{}' with suspicion score {}",
+                in_file.display(),
+                score
+            );
+            return Ok(());
+        }
+
+        // Hold the lock so that output for different files is not interleaved
+        let _stdout = std::io::stdout().lock();
+
+        println!("{}: suspicion score {}/100", in_file.display(), score);
+        for finding in analysis.findings {
+            println!("    {}", finding.description);
+        }
+
+        Ok(())
+    })
+}
+
+pub(crate) fn bitplane(opts: &args::Bitplane) -> Result<(), Error> {
+    use std::io::Write;
+
+    let image =
+        Image::decode(&read_png(&opts.in_file)?).map_err(|error| error.in_file(&opts.in_file))?;
+
+    let (rendered, description) = match opts.amplify {
+        Some(bits) => (
+            bitplane::amplify(&image, bits)?,
+            match bits {
+                1 => "the least significant bit".to_string(),
+                _ => format!("the {bits} least significant bits"),
+            },
+        ),
+        None => (
+            bitplane::bit_plane(&image, opts.channel.channel(), opts.bit)?,
+            format!("bit {} of the {:?} channel", opts.bit, opts.channel).to_lowercase(),
+        ),
+    };
+    write_png(&opts.out, &opts.encoding.new_png(&rendered).as_bytes())?;
+
+    writeln!(
+        status_output(&opts.out),
+        "Rendered {} of file '{}' as '{}'",
+        description,
+        opts.in_file.display(),
+        opts.out.display()
+    )?;
+
+    Ok(())
+}
+
+impl args::Pattern {
+    fn pattern(self) -> create::Pattern {
+        match self {
+            Self::Noise => create::Pattern::Noise,
+            Self::Gradient => create::Pattern::Gradient,
+            Self::Solid => create::Pattern::Solid,
+        }
+    }
+}
+
+pub(crate) fn create(opts: &args::Create) -> Result<(), Error> {
+    use std::io::Write;
+
+    if let Some(data_file) = &opts.data_image {
+        let data = read_bytes(data_file)?;
+        let image = create::data_image(&data, opts.width)?;
+        write_png(&opts.out_file, &opts.encoding.new_png(&image).as_bytes())?;
+
+        writeln!(
+            status_output(&opts.out_file),
+            "Stored {} bytes in a {}x{} image in file '{}'",
+            data.len(),
+            image.width(),
+            image.height(),
+            opts.out_file.display()
+        )?;
+        return Ok(());
+    }
+
+    let message = opts
+        .message
+        .as_deref()
+        .expect("A message is required without --data-image");
+    let passphrase = opts
+        .hiding
+        .passphrase
+        .as_deref()
+        .ok_or(Error::PassphraseRequired)?;
+
+    let image = create::pattern_image(
+        opts.pattern.pattern(),
+        opts.width.unwrap_or(512),
+        opts.height,
+    );
+    let mut png = opts.encoding.new_png(&image);
+    let capacity = hide_message(
+        &mut png,
+        &image,
+        message.as_bytes(),
+        passphrase,
+        &opts.hiding,
+        &opts.encoding,
+    )?;
+    write_png(&opts.out_file, &png.as_bytes())?;
+
+    writeln!(
+        status_output(&opts.out_file),
+        "Hid {} of {} bytes in new file '{}'",
+        message.len(),
+        capacity,
+        opts.out_file.display()
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::temp_dir;
+    use crate::image::{ColourType, Header};
+    use clap::Parser;
+    use std::str::FromStr;
+
+    /// Returns a PNG of a 2x2 greyscale image with an additional chunk for each of the given chunk
+    /// types and data.
+    fn testing_png(chunks: &[(&str, &[u8])]) -> Png {
+        let header = Header {
+            width: 2,
+            height: 2,
+            bit_depth: 8,
+            colour_type: ColourType::Greyscale,
+            interlaced: false,
+        };
+        let encoding = args::Encoding {
+            filter: args::Filter::Adaptive,
+            level: 9,
+            idat_size: 8192,
+        };
+        let mut png = encoding.new_png(&Image::new(header, vec![0, 64, 128, 255]));
+        for (chunk_type, data) in chunks {
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec());
+            png.append_chunk(chunk).unwrap();
+        }
+
+        png
+    }
+
+    fn write_testing_png(path: &Path, chunks: &[(&str, &[u8])]) {
+        std::fs::write(path, testing_png(chunks).as_bytes()).unwrap();
+    }
+
+    /// Returns the types of the chunks of the PNG file at the given path.
+    fn chunk_types(path: &Path) -> Vec<String> {
+        read_png(path)
+            .unwrap()
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    fn path_str(path: &Path) -> &str {
+        path.to_str().unwrap()
+    }
+
+    #[test]
+    fn test_remove_out_file() {
+        let dir = temp_dir("remove-out-file");
+        let (in_file, out_file) = (dir.join("in.png"), dir.join("out.png"));
+        write_testing_png(&in_file, &[("ruSt", b"message")]);
+
+        let opts = args::Remove::parse_from([
+            "remove",
+            path_str(&in_file),
+            "ruSt",
+            "--out-file",
+            path_str(&out_file),
+        ]);
+        remove(&opts).unwrap();
+
+        assert!(chunk_types(&in_file).contains(&"ruSt".to_string()));
+        assert_eq!(chunk_types(&out_file), ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_remove_several_types() {
+        let dir = temp_dir("remove-several-types");
+        let file = dir.join("file.png");
+        write_testing_png(
+            &file,
+            &[
+                ("ruSt", b"first"),
+                ("keEp", b"kept"),
+                ("teSt", b"test"),
+                ("ruSt", b"second"),
+            ],
+        );
+
+        // Every chunk of each type is removed, and missing types don't stop the others
+        let opts = args::Remove::parse_from(["remove", path_str(&file), "ruSt", "teSt", "miSs"]);
+        remove(&opts).unwrap();
+        assert_eq!(chunk_types(&file), ["IHDR", "IDAT", "keEp", "IEND"]);
+
+        let opts = args::Remove::parse_from(["remove", path_str(&file), "ruSt", "miSs"]);
+        assert!(matches!(
+            remove(&opts),
+            Err(Error::InFile { source, .. }) if matches!(*source, Error::ChunkTypeNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_remove_all_private() {
+        let dir = temp_dir("remove-all-private");
+        let file = dir.join("file.png");
+        write_testing_png(
+            &file,
+            &[
+                ("ruSt", b"message"),
+                ("tEXt", b"Comment\0text"),
+                ("teSt", b""),
+            ],
+        );
+
+        let opts = args::Remove::parse_from(["remove", path_str(&file), "--all-private"]);
+        remove(&opts).unwrap();
+        assert_eq!(chunk_types(&file), ["IHDR", "IDAT", "tEXt", "IEND"]);
+    }
+
+    #[test]
+    fn test_strip_preset_keeps() {
+        let chunk_type = |chunk_type: &str| ChunkType::from_str(chunk_type).unwrap();
+
+        for preset in [
+            args::StripPreset::KeepOnlyCritical,
+            args::StripPreset::KeepColourManagement,
+            args::StripPreset::RemovePrivateOnly,
+        ] {
+            assert!(preset.keeps(&chunk_type("IHDR")));
+            assert!(preset.keeps(&chunk_type("PLTE")));
+            assert!(!preset.keeps(&chunk_type("ruSt")));
+        }
+
+        assert!(!args::StripPreset::KeepOnlyCritical.keeps(&chunk_type("tRNS")));
+        assert!(!args::StripPreset::KeepOnlyCritical.keeps(&chunk_type("tEXt")));
+        assert!(args::StripPreset::KeepColourManagement.keeps(&chunk_type("tRNS")));
+        assert!(args::StripPreset::KeepColourManagement.keeps(&chunk_type("iCCP")));
+        assert!(!args::StripPreset::KeepColourManagement.keeps(&chunk_type("tEXt")));
+        assert!(args::StripPreset::RemovePrivateOnly.keeps(&chunk_type("tEXt")));
+    }
+
+    #[test]
+    fn test_strip() {
+        let dir = temp_dir("strip");
+        let (in_file, out_file) = (dir.join("in.png"), dir.join("out.png"));
+        let mut png = testing_png(&[
+            ("tEXt", b"Comment\0text"),
+            ("gAMA", &[0, 0, 177, 143]),
+            ("ruSt", b"message"),
+        ]);
+        png = Png::try_from([png.as_bytes(), b"trailing".to_vec()].concat().as_slice()).unwrap();
+        std::fs::write(&in_file, png.as_bytes()).unwrap();
+
+        let opts = args::Strip::parse_from([
+            "strip",
+            path_str(&in_file),
+            "--out-file",
+            path_str(&out_file),
+            "--keep",
+            "gAMA",
+        ]);
+        strip(&opts).unwrap();
+        assert_eq!(chunk_types(&out_file), ["IHDR", "IDAT", "gAMA", "IEND"]);
+        assert!(read_png(&out_file).unwrap().trailing_data().is_empty());
+        assert_eq!(std::fs::read(&in_file).unwrap(), png.as_bytes());
+
+        let opts = args::Strip::parse_from([
+            "strip",
+            path_str(&in_file),
+            "--preset",
+            "remove-private-only",
+            "--remove",
+            "gAMA",
+            "--keep-trailing-data",
+        ]);
+        strip(&opts).unwrap();
+        assert_eq!(chunk_types(&in_file), ["IHDR", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(read_png(&in_file).unwrap().trailing_data(), b"trailing");
+
+        let opts = args::Strip::parse_from(["strip", path_str(&in_file), "--remove", "IDAT"]);
+        assert!(matches!(strip(&opts), Err(Error::CriticalChunkType(_))));
+    }
+
+    #[test]
+    fn test_strip_multiple_files() {
+        let dir = temp_dir("strip-multiple-files");
+        let (a, b, out_dir) = (dir.join("a.png"), dir.join("b.png"), dir.join("out"));
+        write_testing_png(&a, &[("tEXt", b"Comment\0a")]);
+        write_testing_png(&b, &[("ruSt", b"b")]);
+        std::fs::create_dir(&out_dir).unwrap();
+
+        let opts = args::Strip::parse_from([
+            "strip",
+            path_str(&a),
+            path_str(&b),
+            "--out-file",
+            path_str(&out_dir),
+        ]);
+        assert_eq!(opts.in_files, [a, b]);
+        strip(&opts).unwrap();
+        assert_eq!(
+            chunk_types(&out_dir.join("a.png")),
+            ["IHDR", "IDAT", "IEND"]
+        );
+        assert_eq!(
+            chunk_types(&out_dir.join("b.png")),
+            ["IHDR", "IDAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_scan_chunks() {
+        let xmp = TextualData::international(text::XMP_KEYWORD, &text::xmp_packet("secret 3"));
+        let mut png = testing_png(&[
+            ("ruSt", b"secret 1"),
+            ("ruSt", &zlib::compress(b"secret 2", 9)),
+            (
+                "ruSt",
+                &Envelope {
+                    compressed: true,
+                    ..Envelope::text("secret 4")
+                }
+                .to_bytes(None)
+                .unwrap(),
+            ),
+            ("ruSt", &[0xff, 0xfe, 0xfd]),
+            ("tEXt", b"Comment\0secret 6"),
+        ]);
+        png.append_chunk(xmp.to_chunk().unwrap()).unwrap();
+        png.append_chunk(
+            TextualData::compressed("Comment", "secret 5")
+                .to_chunk()
+                .unwrap(),
+        )
+        .unwrap();
+
+        // Without a type pattern, only chunks which could contain messages are listed
+        let opts = args::Scan::parse_from(["scan", "file.png"]);
+        let found: Vec<_> = scan_chunks(&opts, &png)
+            .into_iter()
+            .map(|(chunk, found)| (chunk.chunk_type().to_string(), found))
+            .collect();
+        assert_eq!(found.len(), 4);
+        assert!(found
+            .iter()
+            .all(|(chunk_type, found)| chunk_type == "ruSt" && found.is_none()));
+
+        // The regular expression is matched against decoded text
+        let opts = args::Scan::parse_from([
+            "scan",
+            "file.png",
+            "--type",
+            "^(ruSt|tEXt|zTXt|iTXt)$",
+            "-e",
+            r"secret \d",
+        ]);
+        let found: Vec<_> = scan_chunks(&opts, &png)
+            .into_iter()
+            .map(|(chunk, found)| (chunk.chunk_type().to_string(), found.unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("ruSt", "secret 1"),
+                ("ruSt", "secret 2"),
+                ("ruSt", "secret 4"),
+                ("tEXt", "secret 6"),
+                ("iTXt", "secret 3"),
+                ("zTXt", "secret 5"),
+            ]
+            .map(|(chunk_type, found)| (chunk_type.to_string(), found.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_carved_file_name() {
+        assert_eq!(
+            carved_file_name(Path::new("dumps/memory.bin"), 0x1234),
+            "memory-0x00001234.png"
+        );
+        assert_eq!(carved_file_name(Path::new("-"), 16), "stdin-0x00000010.png");
+        assert_eq!(
+            carved_file_name(Path::new(".."), 0),
+            "carved-0x00000000.png"
+        );
+    }
+
+    #[test]
+    fn test_extract() {
+        let dir = temp_dir("extract");
+        let (in_file, out_dir) = (dir.join("in.png"), dir.join("out"));
+        write_testing_png(
+            &in_file,
+            &[
+                ("ruSt", b"plain"),
+                ("coMp", &zlib::compress(b"compressed", 9)),
+                (
+                    "enCr",
+                    &crypto::encrypt(&zlib::compress(b"encrypted", 9), "passphrase"),
+                ),
+                ("biNa", &[0xff, 0x00]),
+                ("tEXt", b"Comment\0text"),
+                ("gAMA", &[0, 0, 177, 143]),
+            ],
+        );
+
+        let opts = args::Extract::parse_from([
+            "extract",
+            path_str(&in_file),
+            "--out",
+            path_str(&out_dir),
+            "--passphrase",
+            "passphrase",
+        ]);
+        extract(&opts).unwrap();
+
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(out_dir.join("manifest.json")).unwrap()).unwrap();
+        let chunks = manifest["chunks"].as_array().unwrap();
+        let summary: Vec<_> = chunks
+            .iter()
+            .map(|chunk| {
+                let payload = &chunk["payload"];
+                let path = payload["path"].as_str().unwrap();
+                (
+                    chunk["type"].as_str().unwrap(),
+                    chunk["index"].as_u64().unwrap(),
+                    path,
+                    std::fs::read(out_dir.join(path)).unwrap(),
+                    payload["compressed"].as_bool().unwrap(),
+                    payload["encrypted"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            [
+                ("ruSt", 2, "002-ruSt.txt", b"plain".to_vec(), false, false),
+                (
+                    "coMp",
+                    3,
+                    "003-coMp.txt",
+                    b"compressed".to_vec(),
+                    true,
+                    false
+                ),
+                ("enCr", 4, "004-enCr.txt", b"encrypted".to_vec(), true, true),
+                ("biNa", 5, "005-biNa.bin", vec![0xff, 0x00], false, false),
+                ("tEXt", 6, "006-tEXt.txt", b"text".to_vec(), false, false),
+            ]
+        );
+        assert_eq!(chunks[4]["payload"]["keyword"], "Comment");
+        assert_eq!(chunks[3]["payload"]["encoding"], "binary");
+
+        // Chunks are located by their byte offset, after the 8-byte PNG header
+        let png = read_png(&in_file).unwrap();
+        let offset = 8 + png.chunks()[..2]
+            .iter()
+            .map(|chunk| chunk.as_bytes().len())
+            .sum::<usize>();
+        assert_eq!(chunks[0]["offset"], offset);
+    }
+
+    #[test]
+    fn test_extract_toml_without_passphrase() {
+        let dir = temp_dir("extract-toml");
+        let (in_file, out_dir) = (dir.join("in.png"), dir.join("out"));
+        let encrypted = crypto::encrypt(b"encrypted", "passphrase");
+        write_testing_png(&in_file, &[("enCr", &encrypted)]);
+
+        let opts = args::Extract::parse_from([
+            "extract",
+            path_str(&in_file),
+            "--out",
+            path_str(&out_dir),
+            "--format",
+            "toml",
+        ]);
+        extract(&opts).unwrap();
+
+        // Encrypted data is extracted as is without the passphrase
+        let manifest = std::fs::read_to_string(out_dir.join("manifest.toml")).unwrap();
+        assert!(manifest.contains("type = \"enCr\"\n"));
+        assert!(manifest.contains("encrypted = true\n"));
+        assert_eq!(
+            std::fs::read(out_dir.join("002-enCr.bin")).unwrap(),
+            encrypted
+        );
+    }
+
+    /// Writes a testing PNG file and the given manifest to a new directory, and returns the
+    /// options to import the manifest into the PNG file.
+    fn import_opts(name: &str, manifest: &str, passphrase: Option<&str>) -> args::Import {
+        let dir = temp_dir(name);
+        let (in_file, manifest_file) = (dir.join("in.png"), dir.join("manifest.toml"));
+        write_testing_png(&in_file, &[("ruSt", b"existing")]);
+        std::fs::write(&manifest_file, manifest).unwrap();
+        std::fs::write(dir.join("message.bin"), [0xff, 0x00]).unwrap();
+
+        let mut args = vec![
+            "import",
+            path_str(&in_file),
+            "--manifest",
+            path_str(&manifest_file),
+        ];
+        if let Some(passphrase) = passphrase {
+            args.extend(["--passphrase", passphrase]);
+        }
+        args::Import::parse_from(args)
+    }
+
+    #[test]
+    fn test_import() {
+        let opts = import_opts(
+            "import",
+            r#"
+[[chunk]]
+type = "enDs"
+text = "end"
+
+[[chunk]]
+type = "stRt"
+text = "start"
+placement = "start"
+
+[[chunk]]
+type = "beFo"
+file = "message.bin"
+placement = "before-data"
+
+[[chunk]]
+type = "afTr"
+text = "after"
+placement = "after-data"
+compress = true
+encrypt = true
+"#,
+            Some("passphrase"),
+        );
+        import(&opts).unwrap();
+
+        assert_eq!(
+            chunk_types(&opts.in_file),
+            ["IHDR", "stRt", "beFo", "IDAT", "afTr", "ruSt", "enDs", "IEND"]
+        );
+
+        let png = read_png(&opts.in_file).unwrap();
+        let data = |chunk_type: &str| png.chunk_by_type(chunk_type).unwrap().data().to_vec();
+        assert_eq!(data("enDs"), b"end");
+        assert_eq!(data("stRt"), b"start");
+        assert_eq!(data("beFo"), [0xff, 0x00]);
+        assert_eq!(
+            zlib::decompress(
+                &crypto::decrypt(&data("afTr"), "passphrase").unwrap(),
+                zlib::MAX_LENGTH
+            )
+            .unwrap(),
+            b"after"
+        );
+    }
+
+    #[test]
+    fn test_import_validates_every_entry() {
+        let valid = "[[chunk]]\ntype = \"vaLd\"\ntext = \"valid\"\n\n";
+        let cases = [
+            (
+                "unmodifiable",
+                "[[chunk]]\ntype = \"tEXt\"\ntext = \"hi\"",
+                "unmodifiable-chunk-type",
+            ),
+            (
+                "existing",
+                "[[chunk]]\ntype = \"ruSt\"\ntext = \"hi\"",
+                "chunk-type-exists",
+            ),
+            (
+                "duplicate",
+                "[[chunk]]\ntype = \"vaLd\"\ntext = \"hi\"",
+                "chunk-type-exists",
+            ),
+            (
+                "sources",
+                "[[chunk]]\ntype = \"soUr\"\ntext = \"hi\"\nfile = \"message.bin\"",
+                "invalid-manifest-entry",
+            ),
+            (
+                "passphrase",
+                "[[chunk]]\ntype = \"enCr\"\ntext = \"hi\"\nencrypt = true",
+                "passphrase-required",
+            ),
+        ];
+
+        for (name, invalid, code) in cases {
+            let opts = import_opts(
+                &format!("import-{name}"),
+                &format!("{valid}{invalid}"),
+                None,
+            );
+            let original = std::fs::read(&opts.in_file).unwrap();
+
+            let error = import(&opts).unwrap_err();
+            assert_eq!(error.code(), code, "{name}: {error:?}");
+            assert_eq!(std::fs::read(&opts.in_file).unwrap(), original, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_describe_png() {
+        let mut bytes = testing_png(&[
+            ("tEXt", b"Comment\0first line\nsecond line"),
+            ("ruSt", b"This is a secret message!"),
+            ("coMp", &zlib::compress(b"compressed", 9)),
+            ("biNa", &[0xff, 0x00]),
+            ("enCr", b"PMe1 looks encrypted"),
+        ])
+        .as_bytes();
+        bytes.extend(b"trailing");
+        let png = Png::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(
+            describe_png(&png).unwrap(),
+            "\
+chunk 0 IHDR length=13 crc=57dd52f8
+    width=2 height=2 bit-depth=8 colour-type=0 interlace=0
+chunk 1 IDAT length=14 crc=535b159f
+chunk 2 tEXt length=30 crc=ed62faf6
+    keyword=Comment
+    | first line
+    | second line
+chunk 3 ruSt length=25 crc=52de2383
+    | This is a secret message!
+chunk 4 coMp length=18 crc=2701e121
+    | compressed
+chunk 5 biNa length=2 crc=c12669a5
+    <binary data, 2 bytes>
+chunk 6 enCr length=20 crc=77e1523c
+    <encrypted>
+chunk 7 IEND length=0 crc=ae426082
+data after IEND length=8 crc=7f1b4204
+"
+        );
+    }
+
+    #[test]
+    fn test_message_location() {
+        let location = |args: &[&str]| {
+            let opts = args::Decode::parse_from([&["decode", "in.png"], args].concat());
+            message_location(
+                opts.chunk_type.as_ref(),
+                &opts.secret,
+                opts.keyword.as_deref(),
+            )
+        };
+
+        assert!(matches!(
+            location(&["ruSt"]),
+            Ok(MessageLocation::Chunk(chunk_type)) if chunk_type.to_string() == "ruSt"
+        ));
+        assert!(matches!(
+            location(&["--secret", "correct horse"]),
+            Ok(MessageLocation::Chunk(chunk_type))
+                if chunk_type == crypto::derive_chunk_type("correct horse", "message")
+        ));
+        assert!(matches!(location(&["derive"]), Err(Error::SecretRequired)));
+        for chunk_type in ["ruSt", "zTXt", "iTXt"] {
+            assert!(matches!(
+                location(&[chunk_type, "--secret", "correct horse"]),
+                Err(Error::IncompatibleOptions(_))
+            ));
+        }
+
+        assert!(matches!(
+            location(&["zTXt"]),
+            Ok(MessageLocation::Camouflaged { keyword, .. }) if keyword == "Comment"
+        ));
+        assert!(matches!(
+            location(&["zTXt", "--keyword", "Software"]),
+            Ok(MessageLocation::Camouflaged { keyword, .. }) if keyword == "Software"
+        ));
+        assert!(matches!(
+            location(&["zTXt", "--keyword", ""]),
+            Err(Error::InvalidKeyword(_))
+        ));
+        for chunk_type in ["ruSt", "iTXt"] {
+            assert!(matches!(
+                location(&[chunk_type, "--keyword", "Software"]),
+                Err(Error::IncompatibleOptions(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_encode_xmp_into_existing_packet() {
+        let dir = temp_dir("encode-xmp");
+        let in_file = dir.join("in.png");
+        let packet = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF \
+            xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\"><rdf:Description \
+            rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmp:CreatorTool=\"pngme\"/>\
+            </rdf:RDF></x:xmpmeta>";
+        let xmp = TextualData::international(text::XMP_KEYWORD, packet);
+        write_testing_png(&in_file, &[("iTXt", xmp.to_chunk().unwrap().data())]);
+
+        let encode_opts =
+            args::Encode::parse_from(["encode", path_str(&in_file), "iTXt", "hidden"]);
+        encode(&encode_opts).unwrap();
+
+        let png = read_png(&in_file).unwrap();
+        let xmp = find_text(&png, "iTXt", text::XMP_KEYWORD).unwrap();
+        assert!(xmp.text.contains("xmp:CreatorTool=\"pngme\""));
+        assert_eq!(text::xmp_description(&xmp.text).as_deref(), Some("hidden"));
+        assert_eq!(chunk_types(&in_file), ["IHDR", "IDAT", "iTXt", "IEND"]);
+
+        let error = encode(&encode_opts).unwrap_err();
+        assert_eq!(error.code(), "xmp-description-exists");
+    }
+
+    #[test]
+    fn test_verify_pixels() {
+        let mut png = testing_png(&[]);
+        let original = pixel_chunks(&png);
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"message".to_vec(),
+        ))
+        .unwrap();
+        verify_pixels(original, &png, Path::new("in.png")).unwrap();
+
+        // Re-encoding the same pixels differently is fine, changing them isn't
+        let original = pixel_chunks(&png);
+        let image = Image::decode(&png).unwrap();
+        png.replace_image_data(
+            &image.encode(FilterStrategy::Fixed(FilterType::Up), 0),
+            8192,
+        );
+        verify_pixels(original, &png, Path::new("in.png")).unwrap();
+
+        let original = pixel_chunks(&png);
+        let mut image = Image::decode(&png).unwrap();
+        image.samples_mut()[0] ^= 1;
+        png.replace_image_data(&image.encode(FilterStrategy::Adaptive, 9), 8192);
+        assert!(matches!(
+            verify_pixels(original, &png, Path::new("in.png")),
+            Err(Error::PixelsChanged(_))
+        ));
+
+        // Input files whose image can't be decoded aren't checked
+        let mut ihdr = Header::from_chunk(&png.chunks()[0]).unwrap();
+        ihdr.width = i32::MAX as u32;
+        ihdr.height = i32::MAX as u32;
+        let huge = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]);
+        verify_pixels(pixel_chunks(&huge), &png, Path::new("huge.png")).unwrap();
+    }
+
+    #[test]
+    fn test_decode_raw_message_starting_like_an_envelope() {
+        let dir = temp_dir("decode-legacy");
+        let in_file = dir.join("in.png");
+        write_testing_png(&in_file, &[("ruSt", b"PMen at work")]);
+
+        let opts = args::Decode::parse_from(["decode", path_str(&in_file), "ruSt"]);
+        decode(&opts).unwrap();
+
+        let png = read_png(&in_file).unwrap();
+        let chunk = png.chunk_by_type("ruSt").unwrap();
+        assert_eq!(chunk_text(chunk).as_deref(), Some("PMen at work"));
+
+        // Corrupted envelopes are still reported as such
+        let mut envelope = Envelope::text("message").to_bytes(None).unwrap();
+        *envelope.last_mut().unwrap() = 0xff;
+        write_testing_png(&in_file, &[("ruSt", &envelope)]);
+        assert_eq!(decode(&opts).unwrap_err().code(), "invalid-envelope");
+    }
+
+    #[test]
+    fn test_encode_raw_with_passphrase() {
+        let dir = temp_dir("encode-raw");
+        let in_file = dir.join("in.png");
+        write_testing_png(&in_file, &[]);
+
+        for args in [
+            &["ruSt", "message", "--raw", "--passphrase", "x"][..],
+            &["zTXt", "message", "--passphrase", "x"],
+        ] {
+            let opts = args::Encode::parse_from([&["encode", path_str(&in_file)], args].concat());
+            assert_eq!(encode(&opts).unwrap_err().code(), "incompatible-options");
+        }
+    }
+
+    #[test]
+    fn test_extract_and_describe_encoded_messages() {
+        let dir = temp_dir("extract-encoded");
+        let (in_file, out_dir) = (dir.join("in.png"), dir.join("out"));
+        write_testing_png(&in_file, &[]);
+
+        for args in [
+            &["ruSt", "hello world"][..],
+            &["enCr", "secret", "--compress", "--passphrase", "passphrase"],
+        ] {
+            let opts = args::Encode::parse_from([&["encode", path_str(&in_file)], args].concat());
+            encode(&opts).unwrap();
+        }
+
+        let png = read_png(&in_file).unwrap();
+        let description = describe_png(&png).unwrap();
+        assert!(description.contains("ruSt length=27"));
+        assert!(description.contains("    | hello world\n"));
+        assert!(description.contains("    <encrypted>\n"));
+        assert_eq!(
+            chunk_text(png.chunk_by_type("ruSt").unwrap()).as_deref(),
+            Some("hello world")
+        );
+
+        let opts = args::Extract::parse_from([
+            "extract",
+            path_str(&in_file),
+            "--out",
+            path_str(&out_dir),
+            "--passphrase",
+            "passphrase",
+        ]);
+        extract(&opts).unwrap();
+
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(out_dir.join("manifest.json")).unwrap()).unwrap();
+        let payloads: Vec<_> = manifest["chunks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|chunk| {
+                let payload = &chunk["payload"];
+                let path = payload["path"].as_str().unwrap();
+                (
+                    path.to_string(),
+                    std::fs::read(out_dir.join(path)).unwrap(),
+                    payload["encoding"].as_str().unwrap().to_string(),
+                    payload["compressed"].as_bool().unwrap(),
+                    payload["encrypted"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            payloads,
+            [
+                (
+                    "002-ruSt.txt".to_string(),
+                    b"hello world".to_vec(),
+                    "utf-8".to_string(),
+                    false,
+                    false
+                ),
+                (
+                    "003-enCr.txt".to_string(),
+                    b"secret".to_vec(),
+                    "utf-8".to_string(),
+                    true,
+                    true
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+
+use crate::error::Error;
+use crate::image::{ColourType, Image};
+
+/// How much the pixels of two images of the same dimensions differ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Comparison {
+    /// Number of pixels in each image.
+    pub(crate) pixels: usize,
+
+    /// Number of pixels which differ in at least one sample.
+    pub(crate) changed_pixels: usize,
+
+    /// Number of samples compared.
+    pub(crate) samples: usize,
+
+    /// Number of samples which differ.
+    pub(crate) changed_samples: usize,
+
+    /// Largest absolute difference between two samples.
+    pub(crate) max_delta: u16,
+
+    /// Bit depth of the samples compared.
+    pub(crate) bit_depth: u8,
+
+    /// Sum of the squared differences between the samples.
+    squared_error: f64,
+}
+
+impl Comparison {
+    pub(crate) fn is_identical(&self) -> bool {
+        self.changed_samples == 0
+    }
+
+    /// Returns the peak signal-to-noise ratio in decibels, or `None` if the images are identical.
+    pub(crate) fn psnr(&self) -> Option<f64> {
+        if self.is_identical() {
+            return None;
+        }
+
+        let max = f64::from((1_u32 << self.bit_depth) - 1);
+        #[allow(clippy::cast_precision_loss)]
+        let mean_squared_error = self.squared_error / self.samples as f64;
+
+        Some(10.0 * (max * max / mean_squared_error).log10())
+    }
+
+    /// Adds the differences between the samples of a pixel in each image.
+    fn add_pixel(&mut self, pixel_a: &[u16], pixel_b: &[u16]) {
+        let mut changed = false;
+        for (&sample_a, &sample_b) in pixel_a.iter().zip(pixel_b) {
+            let delta = sample_a.abs_diff(sample_b);
+            self.samples += 1;
+            if delta != 0 {
+                changed = true;
+                self.changed_samples += 1;
+                self.max_delta = self.max_delta.max(delta);
+                self.squared_error += f64::from(delta) * f64::from(delta);
+            }
+        }
+        self.changed_pixels += usize::from(changed);
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_identical() {
+            return write!(f, "all {} pixels identical", self.pixels);
+        }
+
+        write!(
+            f,
+            "{} of {} pixels changed, {} of {} samples by at most {} (PSNR {:.2} dB)",
+            self.changed_pixels,
+            self.pixels,
+            self.changed_samples,
+            self.samples,
+            self.max_delta,
+            self.psnr().unwrap_or(f64::INFINITY)
+        )
+    }
+}
+
+/// Compares the pixels of two images.
+///
+/// Images of the same colour type and bit depth are compared sample by sample. Other images, and
+/// indexed-colour images whose palettes may be ordered differently, are compared by the RGBA
+/// colours of their pixels, at a bit depth of 8 if neither image has more than 8 bits per sample
+/// and 16 otherwise.
+pub(crate) fn compare(a: &Image, b: &Image) -> Result<Comparison, Error> {
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        return Err(Error::PixelsDiffer(format!(
+            "different dimensions ({}x{} and {}x{})",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        )));
+    }
+
+    let (a_header, b_header) = (a.header(), b.header());
+    let same_format = a_header.colour_type == b_header.colour_type
+        && a_header.bit_depth == b_header.bit_depth
+        && a_header.colour_type != ColourType::Indexed;
+
+    let bit_depth = if same_format {
+        a_header.bit_depth
+    } else if a_header.bit_depth <= 8 && b_header.bit_depth <= 8 {
+        8
+    } else {
+        16
+    };
+
+    let mut comparison = Comparison {
+        pixels: a.width() * a.height(),
+        changed_pixels: 0,
+        samples: 0,
+        changed_samples: 0,
+        max_delta: 0,
+        bit_depth,
+        squared_error: 0.0,
+    };
+    if same_format {
+        let channels = a.channels();
+        for (pixel_a, pixel_b) in a
+            .samples()
+            .chunks_exact(channels)
+            .zip(b.samples().chunks_exact(channels))
+        {
+            comparison.add_pixel(pixel_a, pixel_b);
+        }
+    } else {
+        // Samples of 8 bits or less are scaled to 16 bits by multiples of 257, so this is exact
+        let scale = if bit_depth == 8 { 257 } else { 1 };
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                let rgba = |image: &Image| image.rgba(x, y).map(|sample| sample / scale);
+                comparison.add_pixel(&rgba(a), &rgba(b));
+            }
+        }
+    }
+
+    Ok(comparison)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Header;
+
+    #[test]
+    fn test_identical() {
+        let a = Image::from_samples(2, 2, ColourType::Greyscale, 8, vec![1, 2, 3, 4]);
+        let comparison = compare(&a, &a).unwrap();
+
+        assert!(comparison.is_identical());
+        assert_eq!(comparison.samples, 4);
+        assert_eq!(comparison.psnr(), None);
+        assert_eq!(comparison.to_string(), "all 4 pixels identical");
+    }
+
+    #[test]
+    fn test_different() {
+        let a = Image::from_samples(
+            2,
+            2,
+            ColourType::GreyscaleAlpha,
+            8,
+            vec![10, 255, 20, 255, 30, 255, 40, 255],
+        );
+        let b = Image::from_samples(
+            2,
+            2,
+            ColourType::GreyscaleAlpha,
+            8,
+            vec![11, 255, 20, 255, 27, 254, 40, 255],
+        );
+        let comparison = compare(&a, &b).unwrap();
+
+        assert_eq!(comparison.changed_pixels, 2);
+        assert_eq!(comparison.changed_samples, 3);
+        assert_eq!(comparison.samples, 8);
+        assert_eq!(comparison.max_delta, 3);
+        assert_eq!(comparison.bit_depth, 8);
+        assert!(comparison
+            .to_string()
+            .starts_with("2 of 4 pixels changed, 3 of 8 samples by at most 3 (PSNR "));
+
+        // The mean squared error is (1 + 9 + 1) / 8
+        let expected = 10.0 * (255.0_f64 * 255.0 / (11.0 / 8.0)).log10();
+        assert!((comparison.psnr().unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_different_formats() {
+        let a = Image::from_samples(2, 2, ColourType::Greyscale, 8, vec![0, 100, 200, 255]);
+        let b = Image::from_samples(
+            2,
+            2,
+            ColourType::Greyscale,
+            16,
+            vec![0, 100 * 257, 200 * 257, 65535],
+        );
+        let comparison = compare(&a, &b).unwrap();
+        assert!(comparison.is_identical());
+        assert_eq!(comparison.bit_depth, 16);
+        assert_eq!(comparison.samples, 16);
+
+        let b = Image::from_samples(2, 2, ColourType::Greyscale, 4, vec![0, 6, 12, 15]);
+        let comparison = compare(&a, &b).unwrap();
+        assert_eq!(comparison.bit_depth, 8);
+        assert_eq!(comparison.changed_pixels, 2);
+        assert_eq!(comparison.max_delta, 204 - 200);
+    }
+
+    #[test]
+    fn test_different_dimensions() {
+        let a = Image::from_samples(2, 2, ColourType::Greyscale, 8, vec![0; 4]);
+        let b = Image::new(
+            Header {
+                width: 4,
+                height: 1,
+                ..*a.header()
+            },
+            vec![0; 4],
+        );
+
+        assert!(matches!(compare(&a, &b), Err(Error::PixelsDiffer(_))));
+    }
+}
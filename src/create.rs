@@ -0,0 +1,212 @@
+use rand::Rng;
+
+use crate::error::Error;
+use crate::image::{ColourType, Header, Image, MAX_SAMPLES};
+
+/// Number of bytes used to store the length of the data in front of it in a data image.
+const LENGTH_PREFIX_LENGTH: usize = 4;
+
+/// Patterns to fill a new image with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Pattern {
+    /// Uniformly random samples.
+    Noise,
+
+    /// Red increasing from left to right and green from top to bottom, with blue filling in.
+    Gradient,
+
+    /// A single mid-grey colour.
+    Solid,
+}
+
+/// Creates an 8-bit RGB image of the given dimensions filled with the pattern.
+pub(crate) fn pattern_image(pattern: Pattern, width: u32, height: u32) -> Image {
+    let header = rgb_header(width, height);
+    let (width, height) = (width as usize, height as usize);
+
+    let samples = match pattern {
+        Pattern::Noise => {
+            let mut rng = rand::thread_rng();
+            (0..width * height * 3)
+                .map(|_| u16::from(rng.gen::<u8>()))
+                .collect()
+        }
+        Pattern::Gradient => {
+            let scale = |position: usize, length: usize| {
+                u16::try_from(position * 255 / length.saturating_sub(1).max(1))
+                    .expect("Positions are scaled to at most 255")
+            };
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .flat_map(|(x, y)| {
+                    let (red, green) = (scale(x, width), scale(y, height));
+                    [red, green, 255 - u16::midpoint(red, green)]
+                })
+                .collect()
+        }
+        Pattern::Solid => vec![128; width * height * 3],
+    };
+
+    Image::new(header, samples)
+}
+
+/// Stores the data as the samples of an 8-bit RGB image, prefixed with its length as a 4-byte
+/// big-endian integer and padded with zeros to fill the last row. The image is as close to square
+/// as possible, unless a width is given.
+pub(crate) fn data_image(data: &[u8], width: Option<u32>) -> Result<Image, Error> {
+    let too_large = |capacity| Error::PayloadTooLarge {
+        length: data.len(),
+        capacity,
+    };
+
+    // Decoders reject images with more samples, so the data couldn't be read back
+    let capacity = MAX_SAMPLES - LENGTH_PREFIX_LENGTH;
+    if data.len() > capacity {
+        return Err(too_large(capacity));
+    }
+
+    let pixels = (LENGTH_PREFIX_LENGTH + data.len()).div_ceil(3);
+    let side = pixels.isqrt() + usize::from(pixels.isqrt().pow(2) < pixels);
+    let width = width.map_or(side, |width| width as usize);
+    let height = pixels.div_ceil(width);
+    if width * height * 3 > MAX_SAMPLES {
+        // Padding the last row takes the image over the limit
+        let rows = MAX_SAMPLES / (width * 3);
+        return Err(too_large(
+            (rows * width * 3).saturating_sub(LENGTH_PREFIX_LENGTH),
+        ));
+    }
+
+    let length = u32::try_from(data.len()).expect("The length is at most MAX_SAMPLES");
+    let mut samples: Vec<u16> = length
+        .to_be_bytes()
+        .into_iter()
+        .chain(data.iter().copied())
+        .map(u16::from)
+        .collect();
+    samples.resize(width * height * 3, 0);
+
+    let header = rgb_header(
+        u32::try_from(width).expect("The width is at most MAX_SAMPLES"),
+        u32::try_from(height).expect("The height is at most MAX_SAMPLES"),
+    );
+
+    Ok(Image::new(header, samples))
+}
+
+/// Recovers the data stored in an image by [`data_image`](data_image).
+pub(crate) fn read_data_image(image: &Image) -> Result<Vec<u8>, Error> {
+    let header = image.header();
+    if header.colour_type != ColourType::Rgb || header.bit_depth != 8 {
+        return Err(Error::UnsupportedImage(
+            "data images are 8-bit RGB images".to_string(),
+        ));
+    }
+
+    let bytes: Vec<u8> = image
+        .samples()
+        .iter()
+        .map(|&sample| sample.to_le_bytes()[0])
+        .collect();
+    if bytes.len() < LENGTH_PREFIX_LENGTH {
+        return Err(Error::PayloadNotFound);
+    }
+
+    let (prefix, rest) = bytes.split_at(LENGTH_PREFIX_LENGTH);
+    let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+    if length > rest.len() {
+        return Err(Error::PayloadNotFound);
+    }
+
+    Ok(rest[..length].to_vec())
+}
+
+/// Returns the header of an 8-bit, non-interlaced RGB image of the given dimensions.
+fn rgb_header(width: u32, height: u32) -> Header {
+    Header {
+        width,
+        height,
+        bit_depth: 8,
+        colour_type: ColourType::Rgb,
+        interlaced: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_image() {
+        let noise = pattern_image(Pattern::Noise, 16, 8);
+        assert_eq!((noise.width(), noise.height()), (16, 8));
+        assert!(noise
+            .samples()
+            .iter()
+            .any(|&sample| sample != noise.samples()[0]));
+
+        let gradient = pattern_image(Pattern::Gradient, 3, 2);
+        assert_eq!(gradient.pixel(0, 0), [0, 0, 255]);
+        assert_eq!(gradient.pixel(2, 1), [255, 255, 0]);
+        assert_eq!(gradient.pixel(1, 0), [127, 0, 192]);
+
+        let solid = pattern_image(Pattern::Solid, 1, 1);
+        assert_eq!(solid.samples(), [128, 128, 128]);
+    }
+
+    #[test]
+    fn test_data_image_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        let image = data_image(&data, None).unwrap();
+        // 260 bytes take 87 pixels, which fit in a 10x9 image
+        assert_eq!((image.width(), image.height()), (10, 9));
+        assert_eq!(read_data_image(&image).unwrap(), data);
+
+        let image = data_image(&data, Some(100)).unwrap();
+        assert_eq!((image.width(), image.height()), (100, 1));
+        assert_eq!(read_data_image(&image).unwrap(), data);
+
+        let image = data_image(&[], None).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert!(read_data_image(&image).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_data_image_too_large() {
+        let capacity = MAX_SAMPLES - LENGTH_PREFIX_LENGTH;
+        assert!(matches!(
+            data_image(&vec![0; capacity + 1], None),
+            Err(Error::PayloadTooLarge { length, capacity: c }) if length == capacity + 1 && c == capacity
+        ));
+
+        // The last row is padded to the full width, which may not fit either
+        let rows = MAX_SAMPLES / (16384 * 3);
+        assert!(matches!(
+            data_image(&vec![0; capacity], Some(16384)),
+            Err(Error::PayloadTooLarge { capacity: c, .. }) if c == rows * 16384 * 3 - LENGTH_PREFIX_LENGTH
+        ));
+    }
+
+    #[test]
+    fn test_read_data_image_invalid() {
+        let mut image = data_image(b"data", None).unwrap();
+        image.samples_mut()[0] = 1;
+        assert!(matches!(
+            read_data_image(&image),
+            Err(Error::PayloadNotFound)
+        ));
+
+        let image = Image::new(
+            Header {
+                colour_type: ColourType::Greyscale,
+                ..rgb_header(1, 1)
+            },
+            vec![0],
+        );
+        assert!(matches!(
+            read_data_image(&image),
+            Err(Error::UnsupportedImage(_))
+        ));
+    }
+}
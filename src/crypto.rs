@@ -0,0 +1,138 @@
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+
+/// Identifies data encrypted by [`encrypt`](encrypt).
+const MAGIC: [u8; 4] = *b"PMe1";
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+/// Number of PBKDF2 iterations used to derive the encryption key from a passphrase.
+const KEY_DERIVATION_ROUNDS: u32 = 100_000;
+
+/// Encrypts `data` with a key derived from `passphrase`.
+/// The output consists of a 4-byte magic number, the random salt used to derive the key, the
+/// random nonce, and the XChaCha20-Poly1305 ciphertext including its authentication tag.
+pub(crate) fn encrypt(data: &[u8], passphrase: &str) -> Vec<u8> {
+    use rand::RngCore;
+
+    let mut salt = [0_u8; SALT_LENGTH];
+    let mut nonce = [0_u8; NONCE_LENGTH];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), data)
+        .expect("Encrypting a buffer in memory should not fail");
+
+    [&MAGIC[..], &salt, &nonce, &ciphertext].concat()
+}
+
+/// Decrypts data encrypted by [`encrypt`](encrypt). Fails if the passphrase is wrong or the data
+/// has been tampered with.
+pub(crate) fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    if !is_encrypted(data) || data.len() < MAGIC.len() + SALT_LENGTH + NONCE_LENGTH {
+        return Err(Error::DecryptionFailed);
+    }
+
+    let (salt, rest) = data[MAGIC.len()..].split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt));
+    cipher
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+/// Returns `true` if the data looks like it was encrypted by [`encrypt`](encrypt).
+pub(crate) fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Derives a chunk type which can be used for messages from a keyed hash of `secret` and `label`,
+/// so that only those who know the secret can tell which chunk contains the message.
+pub(crate) fn derive_chunk_type(secret: &str, label: &str) -> ChunkType {
+    use hmac::{Hmac, Mac};
+
+    let mut mac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(label.as_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let letters = [hash[0], hash[1], hash[2], hash[3]].map(|byte| b'a' + byte % 26);
+    ChunkType::try_from(letters)
+        .expect("Lowercase ASCII letters form a valid chunk type")
+        .to_modifiable()
+}
+
+/// Derives a seed for a keyed pseudo-random number generator from `passphrase`. Different
+/// `purpose`s yield unrelated seeds for the same passphrase.
+pub(crate) fn derive_seed(passphrase: &str, purpose: &str) -> [u8; 32] {
+    derive_key(passphrase, purpose.as_bytes()).into()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(
+        passphrase.as_bytes(),
+        salt,
+        KEY_DERIVATION_ROUNDS,
+        &mut key,
+    );
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = b"This is where your secret message will be!";
+        let encrypted = encrypt(data, "passphrase");
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt(&encrypted, "passphrase").unwrap(), data);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let encrypted = encrypt(b"secret", "passphrase");
+        assert!(decrypt(&encrypted, "wrong passphrase").is_err());
+    }
+
+    #[test]
+    fn test_tampered_data() {
+        let mut encrypted = encrypt(b"secret", "passphrase");
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(decrypt(&encrypted, "passphrase").is_err());
+    }
+
+    #[test]
+    fn test_unencrypted_data() {
+        assert!(!is_encrypted(b"secret"));
+        assert!(decrypt(b"secret", "passphrase").is_err());
+    }
+
+    #[test]
+    fn test_derive_chunk_type() {
+        let chunk_type = derive_chunk_type("secret", "label");
+        assert!(chunk_type.is_modifiable());
+        assert_eq!(chunk_type, derive_chunk_type("secret", "label"));
+        assert_ne!(chunk_type, derive_chunk_type("secret", "other label"));
+        assert_ne!(chunk_type, derive_chunk_type("other secret", "label"));
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        let seed = derive_seed("passphrase", "purpose");
+        assert_eq!(seed, derive_seed("passphrase", "purpose"));
+        assert_ne!(seed, derive_seed("passphrase", "other purpose"));
+        assert_ne!(seed, derive_seed("other passphrase", "purpose"));
+    }
+}
@@ -0,0 +1,279 @@
+use crate::chunk::Chunk;
+
+/// A difference between the chunks of two PNG files.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum ChunkChange {
+    /// The chunk at this position in the first file is missing from the second file.
+    Removed(usize),
+
+    /// The chunk at this position in the second file is missing from the first file.
+    Added(usize),
+
+    /// The chunk was moved from a position in the first file to a position in the second file.
+    Reordered(usize, usize),
+
+    /// The chunk at a position in the first file was replaced by a chunk of the same type with
+    /// different data at a position in the second file.
+    Modified(usize, usize),
+}
+
+/// Aligns the chunks of two PNG files and returns the differences between them.
+/// Identical chunks are first aligned in order. Of the remaining chunks, identical chunks are
+/// reported as reordered, and chunks of the same type are paired up in order and reported as
+/// modified. All other chunks are reported as added or removed.
+pub(crate) fn diff_chunks(a: &[Chunk], b: &[Chunk]) -> Vec<ChunkChange> {
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    for (i, j) in longest_common_subsequence(a, b) {
+        a_matched[i] = true;
+        b_matched[j] = true;
+    }
+
+    let mut changes = Vec::new();
+    let mut pair_up = |changes: &mut Vec<ChunkChange>,
+                       is_pair: &dyn Fn(&Chunk, &Chunk) -> bool,
+                       change: fn(usize, usize) -> ChunkChange| {
+        for i in 0..a.len() {
+            if a_matched[i] {
+                continue;
+            }
+
+            if let Some(j) = (0..b.len()).find(|&j| !b_matched[j] && is_pair(&a[i], &b[j])) {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                changes.push(change(i, j));
+            }
+        }
+    };
+
+    pair_up(&mut changes, &|a, b| a == b, ChunkChange::Reordered);
+    pair_up(
+        &mut changes,
+        &|a, b| a.chunk_type() == b.chunk_type(),
+        ChunkChange::Modified,
+    );
+
+    changes.extend(
+        (0..a.len())
+            .filter(|&i| !a_matched[i])
+            .map(ChunkChange::Removed),
+    );
+    changes.extend(
+        (0..b.len())
+            .filter(|&j| !b_matched[j])
+            .map(ChunkChange::Added),
+    );
+
+    changes
+}
+
+/// A line in the difference between two texts.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum LineChange<'a> {
+    Unchanged(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Returns the line by line difference between two texts.
+pub(crate) fn diff_lines<'a>(a: &'a str, b: &'a str) -> Vec<LineChange<'a>> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in longest_common_subsequence(&a, &b)
+        .into_iter()
+        .chain(std::iter::once((a.len(), b.len())))
+    {
+        changes.extend(a[i..next_i].iter().map(|line| LineChange::Removed(line)));
+        changes.extend(b[j..next_j].iter().map(|line| LineChange::Added(line)));
+        if next_i < a.len() {
+            changes.push(LineChange::Unchanged(a[next_i]));
+        }
+        i = next_i + 1;
+        j = next_j + 1;
+    }
+
+    changes
+}
+
+/// Returns the pairs of indices of the elements in the longest common subsequence of `a` and `b`.
+/// Uses Hirschberg's algorithm, so that memory use is linear in the length of the inputs.
+fn longest_common_subsequence<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    push_common_subsequence(a, b, (0, 0), &mut pairs);
+
+    pairs
+}
+
+/// Pushes the pairs of indices of the longest common subsequence of `a` and `b` to `pairs`, in
+/// order, with `offset` added to the indices of `a` and `b`.
+fn push_common_subsequence<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    pairs.extend((0..prefix).map(|k| (offset.0 + k, offset.1 + k)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let offset = (offset.0 + prefix, offset.1 + prefix);
+
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if a.len() == 1 {
+        if let Some(j) = b.iter().position(|element| element == &a[0]) {
+            pairs.push((offset.0, offset.1 + j));
+        }
+    } else if !a.is_empty() && !b.is_empty() {
+        // Split `a` in half and `b` where the common subsequences of both halves are longest
+        let middle = a.len() / 2;
+        let forward = common_subsequence_lengths(&a[..middle], b);
+        let backward = common_subsequence_lengths(a[middle..].iter().rev(), b.iter().rev());
+        let mut split = 0;
+        for j in 1..=b.len() {
+            if forward[j] + backward[b.len() - j] > forward[split] + backward[b.len() - split] {
+                split = j;
+            }
+        }
+
+        push_common_subsequence(&a[..middle], &b[..split], offset, pairs);
+        push_common_subsequence(
+            &a[middle..],
+            &b[split..],
+            (offset.0 + middle, offset.1 + split),
+            pairs,
+        );
+    }
+
+    let end = (offset.0 + a.len(), offset.1 + b.len());
+    pairs.extend((0..suffix).map(|k| (end.0 + k, end.1 + k)));
+}
+
+/// Returns the lengths of the longest common subsequences of `a` and each prefix of `b`, from the
+/// empty prefix to the whole of `b`.
+fn common_subsequence_lengths<'a, T: PartialEq + 'a>(
+    a: impl IntoIterator<Item = &'a T>,
+    b: impl IntoIterator<Item = &'a T>,
+) -> Vec<usize> {
+    let b: Vec<&T> = b.into_iter().collect();
+    let mut lengths = vec![0; b.len() + 1];
+    for element in a {
+        let mut diagonal = 0;
+        for (j, &other) in b.iter().enumerate() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = if element == other {
+                diagonal + 1
+            } else {
+                above.max(lengths[j])
+            };
+            diagonal = above;
+        }
+    }
+
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            data.as_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_identical_chunks() {
+        let a = vec![
+            chunk_from_strings("IHDR", ""),
+            chunk_from_strings("IEND", ""),
+        ];
+        let b = vec![
+            chunk_from_strings("IHDR", ""),
+            chunk_from_strings("IEND", ""),
+        ];
+        assert!(diff_chunks(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_changes() {
+        let a = vec![
+            chunk_from_strings("IHDR", ""),
+            chunk_from_strings("aaAa", "A"),
+            chunk_from_strings("bbBb", "B"),
+            chunk_from_strings("ccCc", "C"),
+            chunk_from_strings("tEXt", "Comment\0Old"),
+            chunk_from_strings("ruSt", "Message"),
+            chunk_from_strings("IEND", ""),
+        ];
+        let b = vec![
+            chunk_from_strings("IHDR", ""),
+            chunk_from_strings("bbBb", "B"),
+            chunk_from_strings("ccCc", "C"),
+            chunk_from_strings("aaAa", "A"),
+            chunk_from_strings("tEXt", "Comment\0New"),
+            chunk_from_strings("abCd", "Added"),
+            chunk_from_strings("IEND", ""),
+        ];
+
+        assert_eq!(
+            diff_chunks(&a, &b),
+            vec![
+                ChunkChange::Reordered(1, 3),
+                ChunkChange::Modified(4, 4),
+                ChunkChange::Removed(5),
+                ChunkChange::Added(5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            diff_lines("first\nsecond\nthird", "first\nchanged\nthird\nfourth"),
+            vec![
+                LineChange::Unchanged("first"),
+                LineChange::Removed("second"),
+                LineChange::Added("changed"),
+                LineChange::Unchanged("third"),
+                LineChange::Added("fourth"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_longest_common_subsequence() {
+        let lcs = |a: &str, b: &str| {
+            let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+            longest_common_subsequence(&a, &b)
+                .into_iter()
+                .map(|(i, j)| {
+                    assert_eq!(a[i], b[j]);
+                    a[i]
+                })
+                .collect::<String>()
+        };
+
+        assert_eq!(lcs("", "abc"), "");
+        assert_eq!(lcs("abc", "abc"), "abc");
+        assert_eq!(lcs("abc", "xyz"), "");
+        assert_eq!(lcs("abcbdab", "bdcaba").len(), 4);
+        assert_eq!(lcs("xmjyauz", "mzjawxu"), "mjau");
+        assert_eq!(
+            lcs("prefix-abc-suffix", "prefix-bca-suffix"),
+            "prefix-bc-suffix"
+        );
+    }
+}
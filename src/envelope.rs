@@ -0,0 +1,364 @@
+use crate::chunk::CRC_32;
+use crate::crypto;
+use crate::error::Error;
+use crate::zlib;
+
+/// Identifies data wrapped in an envelope.
+const MAGIC: [u8; 4] = *b"PMen";
+
+/// Version of the envelope format written by [`Envelope::to_bytes`].
+const VERSION: u8 = 1;
+
+/// Length of the fixed part of the header: magic number, version, flags, content type, original
+/// length, checksum and the number of optional fields.
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 1 + 1 + 4 + 4 + 1;
+
+/// The body is compressed with zlib.
+const FLAG_COMPRESSED: u8 = 0b0001;
+
+/// The body is encrypted with [`crypto::encrypt`](crypto::encrypt), after compressing it.
+const FLAG_ENCRYPTED: u8 = 0b0010;
+
+/// The body is one of several fragments of a message. Reserved, not supported yet.
+const FLAG_FRAGMENTED: u8 = 0b0100;
+
+/// The envelope is signed. Reserved, not supported yet.
+const FLAG_SIGNED: u8 = 0b1000;
+
+/// What kind of data a message consists of.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ContentType {
+    Binary,
+    Text,
+}
+
+impl ContentType {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Binary),
+            1 => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Binary => 0,
+            Self::Text => 1,
+        }
+    }
+}
+
+/// An optional field of an envelope, identified by its tag. Fields with unknown tags are kept
+/// as they are, so that newer versions can add fields without breaking older ones.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Field {
+    pub(crate) tag: u8,
+    pub(crate) value: Vec<u8>,
+}
+
+/// A message together with a description of how it is stored, so that it can be decoded without
+/// knowing how it was encoded.
+///
+/// Envelopes are stored as a 4-byte magic number (`PMen`), the version of the format, a byte of
+/// flags, the content type, the length of the message and the CRC-32 of the body as 4-byte
+/// big-endian integers, the number of optional fields, the optional fields themselves (a tag, a
+/// 2-byte big-endian length and the value each), and finally the body. The body is the message,
+/// compressed and encrypted if the flags say so.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Envelope {
+    pub(crate) content_type: ContentType,
+    pub(crate) fields: Vec<Field>,
+    pub(crate) message: Vec<u8>,
+
+    /// Whether the body is compressed.
+    pub(crate) compressed: bool,
+
+    /// Whether the body is encrypted.
+    pub(crate) encrypted: bool,
+}
+
+impl Envelope {
+    /// Creates an envelope for a text message, without any optional fields.
+    pub(crate) fn text(message: &str) -> Self {
+        Self {
+            content_type: ContentType::Text,
+            fields: Vec::new(),
+            message: message.as_bytes().to_vec(),
+            compressed: false,
+            encrypted: false,
+        }
+    }
+
+    /// Returns `true` if the data starts like an envelope. Data which doesn't is a raw message,
+    /// written before envelopes were introduced.
+    pub(crate) fn is_envelope(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC)
+    }
+
+    /// Serializes the envelope, compressing the message if `compressed` is set and encrypting it
+    /// with the passphrase if `encrypted` is set. Fails if the message is to be encrypted but no
+    /// passphrase is given.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the message is longer than 4 GiB, there are more than 255 optional fields, or an
+    /// optional field is longer than 64 KiB.
+    pub(crate) fn to_bytes(&self, passphrase: Option<&str>) -> Result<Vec<u8>, Error> {
+        let mut flags = 0;
+        let mut body = self.message.clone();
+        if self.compressed {
+            flags |= FLAG_COMPRESSED;
+            body = zlib::compress(&body, 9);
+        }
+        if self.encrypted {
+            flags |= FLAG_ENCRYPTED;
+            body = crypto::encrypt(&body, passphrase.ok_or(Error::PassphraseRequired)?);
+        }
+
+        let length = u32::try_from(self.message.len()).expect("The message fits in an envelope");
+        let field_count = u8::try_from(self.fields.len()).expect("The fields fit in an envelope");
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, flags, self.content_type.to_byte()]);
+        bytes.extend(length.to_be_bytes());
+        bytes.extend(CRC_32.checksum(&body).to_be_bytes());
+        bytes.push(field_count);
+        for field in &self.fields {
+            let field_length =
+                u16::try_from(field.value.len()).expect("The field fits in an envelope");
+            bytes.push(field.tag);
+            bytes.extend(field_length.to_be_bytes());
+            bytes.extend(&field.value);
+        }
+        bytes.extend(body);
+
+        Ok(bytes)
+    }
+
+    /// Parses an envelope, decrypting the message with the passphrase if it is encrypted and
+    /// decompressing it if it is compressed.
+    pub(crate) fn from_bytes(data: &[u8], passphrase: Option<&str>) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::InvalidEnvelope(message.to_string());
+
+        if !Self::is_envelope(data) {
+            return Err(invalid("the magic number is missing"));
+        }
+        if data.len() < HEADER_LENGTH {
+            return Err(invalid("the header is truncated"));
+        }
+
+        let version = data[4];
+        if version != VERSION {
+            return Err(Error::InvalidEnvelope(format!(
+                "version {version} is not supported"
+            )));
+        }
+
+        let flags = data[5];
+        if flags & FLAG_FRAGMENTED != 0 {
+            return Err(invalid("fragmented messages are not supported"));
+        }
+        if flags & FLAG_SIGNED != 0 {
+            return Err(invalid("signed messages are not supported"));
+        }
+        if flags & !(FLAG_COMPRESSED | FLAG_ENCRYPTED) != 0 {
+            return Err(Error::InvalidEnvelope(format!(
+                "unknown flags {flags:#010b}"
+            )));
+        }
+
+        let content_type = ContentType::from_byte(data[6])
+            .ok_or_else(|| Error::InvalidEnvelope(format!("unknown content type {}", data[6])))?;
+        let length = u32::from_be_bytes([data[7], data[8], data[9], data[10]]) as usize;
+        if length > zlib::MAX_LENGTH {
+            return Err(Error::InvalidEnvelope(format!(
+                "a message of {length} bytes is too long"
+            )));
+        }
+        let checksum = u32::from_be_bytes([data[11], data[12], data[13], data[14]]);
+
+        let mut fields = Vec::new();
+        let mut rest = &data[HEADER_LENGTH..];
+        for _ in 0..data[15] {
+            if rest.len() < 3 {
+                return Err(invalid("an optional field is truncated"));
+            }
+            let field_length = usize::from(u16::from_be_bytes([rest[1], rest[2]]));
+            let value = rest
+                .get(3..3 + field_length)
+                .ok_or_else(|| invalid("an optional field is truncated"))?;
+
+            fields.push(Field {
+                tag: rest[0],
+                value: value.to_vec(),
+            });
+            rest = &rest[3 + field_length..];
+        }
+
+        if CRC_32.checksum(rest) != checksum {
+            return Err(invalid("the checksum doesn't match"));
+        }
+
+        let encrypted = flags & FLAG_ENCRYPTED != 0;
+        let mut message = rest.to_vec();
+        if encrypted {
+            let passphrase = passphrase.ok_or(Error::PassphraseRequired)?;
+            message = crypto::decrypt(&message, passphrase)?;
+        }
+        let compressed = flags & FLAG_COMPRESSED != 0;
+        if compressed {
+            message = zlib::decompress(&message, length)?;
+        }
+
+        if message.len() != length {
+            return Err(Error::InvalidEnvelope(format!(
+                "expected a message of {length} bytes, found {}",
+                message.len()
+            )));
+        }
+
+        Ok(Self {
+            content_type,
+            fields,
+            message,
+            compressed,
+            encrypted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for compress in [false, true] {
+            let envelope = Envelope {
+                compressed: compress,
+                ..Envelope::text("This is a secret message!")
+            };
+            let bytes = envelope.to_bytes(None).unwrap();
+            assert!(Envelope::is_envelope(&bytes));
+
+            let parsed = Envelope::from_bytes(&bytes, None).unwrap();
+            assert_eq!(parsed.message, envelope.message);
+            assert_eq!(parsed.content_type, ContentType::Text);
+            assert_eq!(parsed.compressed, compress);
+            assert!(!parsed.encrypted);
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let bytes = Envelope::text("hi").to_bytes(None).unwrap();
+        let crc = CRC_32.checksum(b"hi").to_be_bytes();
+
+        assert_eq!(
+            bytes,
+            [
+                &b"PMen"[..],
+                &[1, 0, 1],
+                &[0, 0, 0, 2],
+                &crc,
+                &[0],
+                &b"hi"[..]
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let envelope = Envelope {
+            compressed: true,
+            encrypted: true,
+            ..Envelope::text("This is a secret message!")
+        };
+        let bytes = envelope.to_bytes(Some("passphrase")).unwrap();
+        assert!(matches!(
+            envelope.to_bytes(None),
+            Err(Error::PassphraseRequired)
+        ));
+
+        let parsed = Envelope::from_bytes(&bytes, Some("passphrase")).unwrap();
+        assert_eq!(parsed.message, envelope.message);
+        assert!(parsed.compressed && parsed.encrypted);
+
+        assert!(matches!(
+            Envelope::from_bytes(&bytes, None),
+            Err(Error::PassphraseRequired)
+        ));
+        assert!(matches!(
+            Envelope::from_bytes(&bytes, Some("wrong passphrase")),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_fields() {
+        let envelope = Envelope {
+            content_type: ContentType::Binary,
+            fields: vec![
+                Field {
+                    tag: 1,
+                    value: b"name.bin".to_vec(),
+                },
+                Field {
+                    tag: 200,
+                    value: Vec::new(),
+                },
+            ],
+            message: vec![0, 1, 2, 255],
+            compressed: false,
+            encrypted: false,
+        };
+
+        let parsed = Envelope::from_bytes(&envelope.to_bytes(None).unwrap(), None).unwrap();
+        assert_eq!(parsed, envelope);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = Envelope::text("hi").to_bytes(None).unwrap();
+        let invalid = |bytes: &[u8]| {
+            matches!(
+                Envelope::from_bytes(bytes, None),
+                Err(Error::InvalidEnvelope(_))
+            )
+        };
+
+        assert!(invalid(b"hi"));
+        assert!(invalid(&bytes[..10]));
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(invalid(&corrupted));
+
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert!(invalid(&newer));
+
+        let mut signed = bytes.clone();
+        signed[5] |= FLAG_SIGNED;
+        assert!(invalid(&signed));
+
+        let mut too_long = Envelope {
+            compressed: true,
+            ..Envelope::text("hi")
+        }
+        .to_bytes(None)
+        .unwrap();
+        too_long[7..11].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Envelope::from_bytes(&too_long, None),
+            Err(Error::InvalidEnvelope(message)) if message.contains("too long")
+        ));
+
+        let mut truncated_field = bytes[..HEADER_LENGTH].to_vec();
+        truncated_field[15] = 1;
+        truncated_field.extend([1, 0, 5, b'a']);
+        assert!(invalid(&truncated_field));
+    }
+}
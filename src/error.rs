@@ -1,8 +1,37 @@
+use std::path::{Path, PathBuf};
+
 use thiserror::Error;
 
+use crate::chunk_type::ChunkType;
+
+/// Errors reported by `pngme`.
+///
+/// Each variant has a stable machine-readable code, returned by [`Error::code`](Error::code), and
+/// possibly a hint on how to resolve it, returned by [`Error::hint`](Error::hint). Errors for a
+/// specific file or chunk are wrapped in [`Error::InFile`](Error::InFile) and
+/// [`Error::InChunk`](Error::InChunk) respectively.
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub(crate) enum Error {
+    #[error("'{}': {source}", .path.display())]
+    InFile {
+        path: PathBuf,
+        #[source]
+        source: Box<Error>,
+    },
+
+    #[error("chunk {index} at byte offset {offset}: {source}")]
+    InChunk {
+        /// Position of the chunk in the list of chunks of the PNG file, starting from zero.
+        index: usize,
+
+        /// Byte offset of the chunk within the PNG file.
+        offset: usize,
+
+        #[source]
+        source: Box<Error>,
+    },
+
     #[error("invalid chunk type '{0}' (expected a 4-character ASCII alphabetic string)")]
     InvalidChunkType(String),
 
@@ -10,23 +39,23 @@ pub(crate) enum Error {
         "invalid chunk type '{0}' (expected a chunk type which is ancillary, private, has a valid \
         reserved bit and is safe-to-copy)"
     )]
-    UnmodifiableChunkType(crate::chunk_type::ChunkType),
+    UnmodifiableChunkType(ChunkType),
 
-    #[error("calculated CRC doesn't match with chunk CRC")]
-    CrcMismatch,
+    #[error("calculated CRC {actual:08x} doesn't match with chunk CRC {expected:08x}")]
+    CrcMismatch { expected: u32, actual: u32 },
 
     #[error("failed to convert chunk data to UTF-8 string")]
     NonUtf8ChunkData(#[from] std::string::FromUtf8Error),
 
-    #[error("failed to convert slice to array")]
-    TryFromSliceError(#[from] std::array::TryFromSliceError),
-
     #[error("chunk of type {0} not found")]
-    ChunkTypeNotFound(crate::chunk_type::ChunkType),
+    ChunkTypeNotFound(ChunkType),
 
     #[error("invalid PNG file size {0} (expected at least 8)")]
     InvalidPngFileSize(usize),
 
+    #[error("unexpected end of data after {length} bytes (expected {expected} bytes)")]
+    UnexpectedEof { length: usize, expected: usize },
+
     #[error("PNG file header doesn't match with standard PNG header")]
     PngHeaderMismatch,
 
@@ -37,5 +66,225 @@ pub(crate) enum Error {
     IendChunkNotFound,
 
     #[error("chunk of type {0} already exists in file!")]
-    ChunkTypeExists(crate::chunk_type::ChunkType),
+    ChunkTypeExists(ChunkType),
+
+    #[error("chunk of type {0} is critical and cannot be removed")]
+    CriticalChunkType(ChunkType),
+
+    #[error("no files found matching '{}'", .0.display())]
+    PathNotFound(PathBuf),
+
+    #[error(
+        "'{}' is not a directory (expected a directory when processing multiple files)",
+        .0.display()
+    )]
+    NotADirectory(PathBuf),
+
+    #[error("failed to process {0} out of {1} files")]
+    BatchFailed(usize, usize),
+
+    #[error("failed to decompress data: {0}")]
+    DecompressionFailed(std::io::Error),
+
+    #[error("invalid textual chunk of type {0}")]
+    InvalidTextualChunk(ChunkType),
+
+    #[error("'{0}' cannot be represented in ISO 8859-1 (Latin-1)")]
+    NonLatin1Text(String),
+
+    #[error("a textual chunk with keyword '{0}' already exists in file!")]
+    KeywordExists(String),
+
+    #[error("invalid keyword '{0}', keywords must be 1 to 79 characters long")]
+    InvalidKeyword(String),
+
+    #[error("the XMP packet already has a description")]
+    XmpDescriptionExists,
+
+    #[error("invalid XMP packet: {0}")]
+    InvalidXmpPacket(String),
+
+    #[error("textual chunk with keyword '{0}' not found")]
+    KeywordNotFound(String),
+
+    #[error("invalid image: {0}")]
+    InvalidImage(String),
+
+    #[error("failed to serialize manifest as JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("failed to serialize manifest as TOML: {0}")]
+    TomlSerializeError(#[from] toml::ser::Error),
+
+    #[error("failed to parse manifest: {0}")]
+    TomlDeserializeError(#[from] toml::de::Error),
+
+    #[error("invalid manifest entry {0}: {1}")]
+    InvalidManifestEntry(usize, String),
+
+    #[error("a passphrase is required to encrypt, decrypt, hide or reveal messages")]
+    PassphraseRequired,
+
+    #[error("a secret is required to derive the chunk type")]
+    SecretRequired,
+
+    #[error("failed to decrypt data (wrong passphrase or corrupted data)")]
+    DecryptionFailed,
+
+    #[error("unsupported image: {0}")]
+    UnsupportedImage(String),
+
+    #[error("payload of {length} bytes doesn't fit in the image (capacity is {capacity} bytes)")]
+    PayloadTooLarge { length: usize, capacity: usize },
+
+    #[error("no hidden payload found (wrong passphrase or options?)")]
+    PayloadNotFound,
+
+    #[error("invalid bit plane: {0}")]
+    InvalidBitPlane(String),
+
+    #[error("pixels differ: {0}")]
+    PixelsDiffer(String),
+
+    #[error("writing the file would change its pixels: {0}")]
+    PixelsChanged(String),
+
+    #[error("invalid message envelope: {0}")]
+    InvalidEnvelope(String),
+
+    #[error("incompatible options: {0}")]
+    IncompatibleOptions(String),
+
+    #[error("git exited with {0}")]
+    GitFailed(std::process::ExitStatus),
+}
+
+impl Error {
+    /// Wraps the error with the path of the file it occurred in, unless it is already wrapped.
+    #[must_use]
+    pub(crate) fn in_file(self, path: &Path) -> Self {
+        match self {
+            Self::InFile { .. } => self,
+            _ => Self::InFile {
+                path: path.to_path_buf(),
+                source: Box::new(self),
+            },
+        }
+    }
+
+    /// Returns a stable machine-readable code identifying the kind of error. Errors wrapped with
+    /// the file or chunk they occurred in return the code of the wrapped error.
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Self::InFile { source, .. } | Self::InChunk { source, .. } => source.code(),
+            Self::InvalidChunkType(_) => "invalid-chunk-type",
+            Self::UnmodifiableChunkType(_) => "unmodifiable-chunk-type",
+            Self::CrcMismatch { .. } => "crc-mismatch",
+            Self::NonUtf8ChunkData(_) => "non-utf8-chunk-data",
+            Self::ChunkTypeNotFound(_) => "chunk-type-not-found",
+            Self::InvalidPngFileSize(_) => "invalid-png-file-size",
+            Self::UnexpectedEof { .. } => "unexpected-eof",
+            Self::PngHeaderMismatch => "png-header-mismatch",
+            Self::IoError(_) => "io-error",
+            Self::IendChunkNotFound => "iend-chunk-not-found",
+            Self::ChunkTypeExists(_) => "chunk-type-exists",
+            Self::CriticalChunkType(_) => "critical-chunk-type",
+            Self::PathNotFound(_) => "path-not-found",
+            Self::NotADirectory(_) => "not-a-directory",
+            Self::BatchFailed(..) => "batch-failed",
+            Self::DecompressionFailed(_) => "decompression-failed",
+            Self::InvalidTextualChunk(_) => "invalid-textual-chunk",
+            Self::NonLatin1Text(_) => "non-latin1-text",
+            Self::KeywordExists(_) => "keyword-exists",
+            Self::InvalidKeyword(_) => "invalid-keyword",
+            Self::XmpDescriptionExists => "xmp-description-exists",
+            Self::InvalidXmpPacket(_) => "invalid-xmp-packet",
+            Self::KeywordNotFound(_) => "keyword-not-found",
+            Self::InvalidImage(_) => "invalid-image",
+            Self::JsonError(_) | Self::TomlSerializeError(_) => "manifest-serialization-failed",
+            Self::TomlDeserializeError(_) => "invalid-manifest",
+            Self::InvalidManifestEntry(..) => "invalid-manifest-entry",
+            Self::PassphraseRequired => "passphrase-required",
+            Self::SecretRequired => "secret-required",
+            Self::DecryptionFailed => "decryption-failed",
+            Self::UnsupportedImage(_) => "unsupported-image",
+            Self::PayloadTooLarge { .. } => "payload-too-large",
+            Self::PayloadNotFound => "payload-not-found",
+            Self::InvalidBitPlane(_) => "invalid-bit-plane",
+            Self::PixelsDiffer(_) => "pixels-differ",
+            Self::PixelsChanged(_) => "pixels-changed",
+            Self::InvalidEnvelope(_) => "invalid-envelope",
+            Self::IncompatibleOptions(_) => "incompatible-options",
+            Self::GitFailed(_) => "git-failed",
+        }
+    }
+
+    /// Returns a hint on how to resolve the error, if there is one.
+    pub(crate) fn hint(&self) -> Option<String> {
+        match self {
+            Self::InFile { source, .. } | Self::InChunk { source, .. } => source.hint(),
+            Self::InvalidChunkType(_) => {
+                Some("chunk types consist of exactly 4 ASCII letters, such as 'ruSt'".to_string())
+            }
+            Self::UnmodifiableChunkType(chunk_type) => Some(format!(
+                "try '{}' instead (lowercase, lowercase, uppercase, lowercase)",
+                chunk_type.to_modifiable()
+            )),
+            Self::ChunkTypeNotFound(_) => Some(
+                "run 'pngme print' to list the chunks which could contain messages".to_string(),
+            ),
+            Self::ChunkTypeExists(_) => Some(
+                "use a different chunk type, or remove the existing chunk with 'pngme remove'"
+                    .to_string(),
+            ),
+            Self::CrcMismatch { .. }
+            | Self::UnexpectedEof { .. }
+            | Self::IendChunkNotFound
+            | Self::InvalidImage(_) => Some(
+                "the PNG file may be corrupted; 'pngme carve' can recover intact PNG files"
+                    .to_string(),
+            ),
+            Self::NonLatin1Text(_) => {
+                Some("use an iTXt chunk instead, which can store any text".to_string())
+            }
+            Self::KeywordExists(_) => Some("use a different keyword".to_string()),
+            Self::XmpDescriptionExists => {
+                Some("use zTXt to camouflage the message instead".to_string())
+            }
+            Self::InvalidPngFileSize(_) | Self::PngHeaderMismatch => {
+                Some("make sure the file is a PNG file".to_string())
+            }
+            Self::PassphraseRequired => Some(
+                "specify the passphrase using '--passphrase' or the PNGME_PASSPHRASE environment \
+                variable"
+                    .to_string(),
+            ),
+            Self::SecretRequired => Some(
+                "specify the secret using '--secret' or the PNGME_SECRET environment variable"
+                    .to_string(),
+            ),
+            Self::CriticalChunkType(_) => {
+                Some("critical chunks are required to display the image".to_string())
+            }
+            Self::NotADirectory(_) => Some("create the directory first".to_string()),
+            Self::UnsupportedImage(_) => Some(
+                "run 'pngme capacity' to see which methods can hide messages in the image, or \
+                hide the message in a chunk with 'pngme encode'"
+                    .to_string(),
+            ),
+            Self::PayloadTooLarge { .. } => Some(
+                "use a larger image or a different method, 'pngme capacity' lists the capacity of \
+                each method"
+                    .to_string(),
+            ),
+            Self::PayloadNotFound => Some(
+                "use the same passphrase, method and options as when hiding the message"
+                    .to_string(),
+            ),
+            Self::PixelsChanged(_) => {
+                Some("the file was left unchanged, pass --no-verify to write it anyway".to_string())
+            }
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,207 @@
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::crypto;
+use crate::error::Error;
+use crate::image::{self, FilterType, Header};
+use crate::png::Png;
+
+/// Separates the seed of the filter type offsets and keystream from other keys derived from the
+/// passphrase.
+const SEED_PURPOSE: &str = "pngme filter types";
+
+/// Number of bytes used to store the length of the payload in front of it. Images have few
+/// scanlines, so two bytes are plenty.
+const LENGTH_PREFIX_LENGTH: usize = 2;
+
+/// Number of payload bits carried by the filter type of each scanline.
+const BITS_PER_SCANLINE: usize = 2;
+
+/// Returns the number of payload bytes which can be hidden in the filter types of an image with
+/// the header.
+pub(crate) fn capacity(header: &Header) -> usize {
+    (header.scanlines() * BITS_PER_SCANLINE / 8).saturating_sub(LENGTH_PREFIX_LENGTH)
+}
+
+/// Chooses a filter type for each scanline of an image with the header, such that they carry the
+/// payload. Encoding the image with them leaves its pixels unchanged.
+///
+/// The payload is prefixed with its length as a 2-byte big-endian integer and encrypted with a
+/// keystream determined by `passphrase`. Each scanline carries two bits, which are added modulo 5
+/// to a pseudo-random offset to get the filter type, so that all five filter types are used
+/// evenly. Scanlines after the payload get random filter types.
+pub(crate) fn embed(
+    header: &Header,
+    payload: &[u8],
+    passphrase: &str,
+) -> Result<Vec<FilterType>, Error> {
+    let capacity = capacity(header);
+    let length = u16::try_from(payload.len())
+        .ok()
+        .filter(|_| payload.len() <= capacity)
+        .ok_or(Error::PayloadTooLarge {
+            length: payload.len(),
+            capacity,
+        })?;
+
+    let mut bytes = [&length.to_be_bytes()[..], payload].concat();
+    let offsets = keyed_offsets(header.scanlines(), &mut bytes, passphrase);
+
+    let mut rng = rand::thread_rng();
+    let symbols = bytes
+        .into_iter()
+        .flat_map(symbols)
+        .chain(std::iter::repeat_with(|| rng.gen_range(0..4)));
+
+    Ok(offsets
+        .into_iter()
+        .zip(symbols)
+        .map(|(offset, symbol)| FilterType::ALL[usize::from((symbol + offset) % 5)])
+        .collect())
+}
+
+/// Recovers a payload hidden by [`embed`](embed) with the same passphrase from the filter types
+/// of the image data of the PNG.
+pub(crate) fn extract(png: &Png, passphrase: &str) -> Result<Vec<u8>, Error> {
+    let filter_types = image::filter_types(png)?;
+    let capacity =
+        (filter_types.len() * BITS_PER_SCANLINE / 8).saturating_sub(LENGTH_PREFIX_LENGTH);
+
+    let mut keystream = vec![0_u8; LENGTH_PREFIX_LENGTH + capacity];
+    let offsets = keyed_offsets(filter_types.len(), &mut keystream, passphrase);
+    let mut symbols = filter_types
+        .iter()
+        .zip(offsets)
+        .map(|(&filter_type, offset)| {
+            let index = FilterType::ALL
+                .iter()
+                .position(|&other| other == filter_type)
+                .expect("Every filter type is in the list");
+            let symbol =
+                (u8::try_from(index).expect("There are five filter types") + 5 - offset) % 5;
+            // The fifth symbol never carries payload bits
+            Some(symbol).filter(|&symbol| symbol < 4)
+        });
+    let mut keystream = keystream.into_iter();
+    let mut read_bytes = |count: usize| -> Option<Vec<u8>> {
+        (0..count)
+            .map(|_| {
+                let byte = symbols
+                    .by_ref()
+                    .take(4)
+                    .try_fold(0_u8, |byte, symbol| Some((byte << 2) | symbol?))?;
+                Some(byte ^ keystream.next()?)
+            })
+            .collect()
+    };
+
+    let length_bytes = read_bytes(LENGTH_PREFIX_LENGTH).ok_or(Error::PayloadNotFound)?;
+    let length = usize::from(u16::from_be_bytes([length_bytes[0], length_bytes[1]]));
+    if length > capacity {
+        return Err(Error::PayloadNotFound);
+    }
+
+    read_bytes(length).ok_or(Error::PayloadNotFound)
+}
+
+/// Encrypts or decrypts the bytes with a keystream determined by the passphrase, and returns a
+/// pseudo-random offset from 0 to 4 for each of the scanlines.
+fn keyed_offsets(scanlines: usize, bytes: &mut [u8], passphrase: &str) -> Vec<u8> {
+    let mut rng = ChaCha20Rng::from_seed(crypto::derive_seed(passphrase, SEED_PURPOSE));
+    let offsets = (0..scanlines).map(|_| rng.gen_range(0..5)).collect();
+
+    let mut keystream = vec![0_u8; bytes.len()];
+    rng.fill_bytes(&mut keystream);
+    for (byte, key) in bytes.iter_mut().zip(keystream) {
+        *byte ^= key;
+    }
+
+    offsets
+}
+
+/// Returns the 2-bit symbols of the byte, most significant first.
+fn symbols(byte: u8) -> impl Iterator<Item = u8> {
+    (0..4).rev().map(move |shift| (byte >> (shift * 2)) & 0b11)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ColourType, Image};
+
+    fn testing_png(height: u32, interlaced: bool, filter_types: Option<&[FilterType]>) -> Png {
+        let header = Header {
+            width: 5,
+            height,
+            bit_depth: 8,
+            colour_type: ColourType::Rgb,
+            interlaced,
+        };
+        let samples = (0..5 * height as usize * 3)
+            .map(|i| u16::try_from((i * 37 + 11) % 256).unwrap())
+            .collect();
+        let image = Image::new(header, samples);
+
+        let mut png = Png::from_chunks(vec![header.to_chunk()]);
+        let image_data = match filter_types {
+            Some(filter_types) => image.encode_with_filters(filter_types, 9),
+            None => image.encode(image::FilterStrategy::Adaptive, 9),
+        };
+        png.replace_image_data(&image_data, 8192);
+
+        png
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let png = testing_png(64, false, None);
+        let header = Header::from_chunk(&png.chunks()[0]).unwrap();
+        let image = Image::decode(&png).unwrap();
+
+        let filter_types = embed(&header, b"hidden", "passphrase").unwrap();
+        assert_eq!(filter_types.len(), 64);
+        assert!(FilterType::ALL
+            .iter()
+            .all(|filter_type| filter_types.contains(filter_type)));
+
+        let stego = testing_png(64, false, Some(&filter_types));
+        assert_eq!(Image::decode(&stego).unwrap().samples(), image.samples());
+        assert_eq!(extract(&stego, "passphrase").unwrap(), b"hidden");
+
+        let payload = extract(&stego, "wrong passphrase");
+        assert!(payload.map_or(true, |payload| payload != b"hidden"));
+    }
+
+    #[test]
+    fn test_interlaced() {
+        let png = testing_png(32, true, None);
+        let header = Header::from_chunk(&png.chunks()[0]).unwrap();
+
+        let filter_types = embed(&header, b"hidden", "passphrase").unwrap();
+        let stego = testing_png(32, true, Some(&filter_types));
+        assert_eq!(extract(&stego, "passphrase").unwrap(), b"hidden");
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png(64, false, None);
+        let header = Header::from_chunk(&png.chunks()[0]).unwrap();
+        assert_eq!(capacity(&header), 64 * 2 / 8 - 2);
+
+        assert!(matches!(
+            embed(&header, &[0; 15], "passphrase"),
+            Err(Error::PayloadTooLarge {
+                length: 15,
+                capacity: 14
+            })
+        ));
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(
+            symbols(0b1110_0100).collect::<Vec<_>>(),
+            [0b11, 0b10, 0b01, 0b00]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::crypto;
+use crate::error::Error;
+use crate::png::Png;
+
+/// Separates the seed of the keystream from other keys derived from the passphrase.
+const SEED_PURPOSE: &str = "pngme idat lengths";
+
+/// Number of bytes used to store the length of the payload in front of it.
+const LENGTH_PREFIX_LENGTH: usize = 4;
+
+/// Returns the number of payload bytes which can be hidden in the lengths of the `IDAT` chunks of
+/// the PNG, when splitting its image data into chunks of about `idat_size` bytes.
+pub(crate) fn capacity(png: &Png, idat_size: usize) -> usize {
+    let image_data_length = png.image_data().len();
+    let max_chunk_length = base_length(idat_size) + 255;
+
+    (image_data_length / max_chunk_length).saturating_sub(LENGTH_PREFIX_LENGTH)
+}
+
+/// Hides the payload in the lengths of the `IDAT` chunks of the PNG, leaving the image data
+/// itself unchanged.
+///
+/// The image data is split again so that the length of each `IDAT` chunk modulo 256 holds one
+/// byte of the payload, prefixed with its length as a 4-byte big-endian integer and encrypted
+/// with a keystream determined by `passphrase`. Chunks are at least `idat_size` bytes long,
+/// rounded down to a multiple of 256, and the image data left over is split into chunks of at
+/// most `idat_size` bytes.
+pub(crate) fn embed(
+    png: &mut Png,
+    payload: &[u8],
+    passphrase: &str,
+    idat_size: usize,
+) -> Result<(), Error> {
+    let image_data = png.image_data();
+    let too_large = || Error::PayloadTooLarge {
+        length: payload.len(),
+        capacity: capacity(png, idat_size),
+    };
+
+    let length = u32::try_from(payload.len()).map_err(|_| too_large())?;
+    let mut bytes = [&length.to_be_bytes()[..], payload].concat();
+    apply_keystream(&mut bytes, passphrase);
+
+    let base_length = base_length(idat_size);
+    let mut pieces = Vec::with_capacity(bytes.len() + 1);
+    let mut rest = &image_data[..];
+    for byte in bytes {
+        let chunk_length = base_length + usize::from(byte);
+        if chunk_length > rest.len() {
+            return Err(too_large());
+        }
+
+        let (piece, remainder) = rest.split_at(chunk_length);
+        pieces.push(piece);
+        rest = remainder;
+    }
+    pieces.extend(rest.chunks(idat_size.max(1)));
+
+    png.replace_idat_chunks(pieces);
+
+    Ok(())
+}
+
+/// Recovers a payload hidden by [`embed`](embed) with the same passphrase.
+pub(crate) fn extract(png: &Png, passphrase: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes: Vec<u8> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .map(|chunk| chunk.length().to_le_bytes()[0])
+        .collect();
+    if bytes.len() < LENGTH_PREFIX_LENGTH {
+        return Err(Error::PayloadNotFound);
+    }
+    apply_keystream(&mut bytes, passphrase);
+
+    let (prefix, rest) = bytes.split_at(LENGTH_PREFIX_LENGTH);
+    let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+    if length > rest.len() {
+        return Err(Error::PayloadNotFound);
+    }
+
+    Ok(rest[..length].to_vec())
+}
+
+/// Returns the length of chunks carrying a zero byte. Chunks carrying a payload byte are longer
+/// by the value of the byte.
+fn base_length(idat_size: usize) -> usize {
+    (idat_size / 256).max(1) * 256
+}
+
+/// Encrypts or decrypts the bytes with a keystream determined by the passphrase.
+fn apply_keystream(bytes: &mut [u8], passphrase: &str) {
+    let mut keystream = vec![0_u8; bytes.len()];
+    ChaCha20Rng::from_seed(crypto::derive_seed(passphrase, SEED_PURPOSE))
+        .fill_bytes(&mut keystream);
+
+    for (byte, key) in bytes.iter_mut().zip(keystream) {
+        *byte ^= key;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_png(image_data_length: usize) -> Png {
+        let chunk = |chunk_type: &str, data: Vec<u8>| {
+            Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+        };
+        let image_data = (0..image_data_length)
+            .map(|i| u8::try_from(i % 251).unwrap())
+            .collect();
+
+        Png::from_chunks(vec![
+            chunk("IHDR", vec![0; 13]),
+            chunk("IDAT", image_data),
+            chunk("IEND", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut png = testing_png(20_000);
+        let image_data = png.image_data();
+
+        embed(&mut png, b"hidden message", "passphrase", 256).unwrap();
+        assert_eq!(png.image_data(), image_data);
+        // The length prefix and the payload take one chunk per byte, followed by the rest
+        assert!(png.chunks().len() > 4 + 14 + 2);
+        assert_eq!(extract(&png, "passphrase").unwrap(), b"hidden message");
+
+        let payload = extract(&png, "wrong passphrase");
+        assert!(payload.map_or(true, |payload| payload != b"hidden message"));
+    }
+
+    #[test]
+    fn test_chunk_lengths() {
+        let mut png = testing_png(100_000);
+        embed(&mut png, b"hidden", "passphrase", 1000).unwrap();
+
+        let lengths: Vec<u32> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+            .map(Chunk::length)
+            .collect();
+        assert!(lengths[..10]
+            .iter()
+            .all(|length| (768..1024).contains(length)));
+        assert!(lengths[10..].iter().all(|&length| length <= 1000));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png(10_000);
+        assert_eq!(capacity(&png, 256), 10_000 / 511 - 4);
+        assert_eq!(capacity(&png, 8192), 0);
+
+        let mut png = testing_png(10_000);
+        let payload = vec![0; 100];
+        assert!(matches!(
+            embed(&mut png, &payload, "passphrase", 256),
+            Err(Error::PayloadTooLarge { .. })
+        ));
+        assert_eq!(png.image_data().len(), 10_000);
+    }
+
+    #[test]
+    fn test_no_payload() {
+        let png = testing_png(1000);
+        assert!(matches!(
+            extract(&png, "passphrase"),
+            Err(Error::PayloadNotFound)
+        ));
+    }
+}
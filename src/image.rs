@@ -0,0 +1,1153 @@
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::Error, png::Png, zlib};
+
+/// Largest number of samples an image may have, so that images with bogus dimensions are rejected
+/// before allocating memory for them.
+pub(crate) const MAX_SAMPLES: usize = 1 << 28;
+
+/// The position of the first pixel and the spacing between pixels in each of the seven passes of
+/// an Adam7 interlaced image, as `(x, y, dx, dy)`.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The way pixels are represented in the image data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ColourType {
+    /// Each pixel is a greyscale sample.
+    Greyscale,
+
+    /// Each pixel is an R, G, B triple.
+    Rgb,
+
+    /// Each pixel is an index into the palette stored in the `PLTE` chunk.
+    Indexed,
+
+    /// Each pixel is a greyscale sample followed by an alpha sample.
+    GreyscaleAlpha,
+
+    /// Each pixel is an R, G, B triple followed by an alpha sample.
+    Rgba,
+}
+
+impl ColourType {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Greyscale),
+            2 => Some(Self::Rgb),
+            3 => Some(Self::Indexed),
+            4 => Some(Self::GreyscaleAlpha),
+            6 => Some(Self::Rgba),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Greyscale => 0,
+            Self::Rgb => 2,
+            Self::Indexed => 3,
+            Self::GreyscaleAlpha => 4,
+            Self::Rgba => 6,
+        }
+    }
+
+    /// Returns the number of samples in each pixel.
+    pub(crate) fn channels(self) -> usize {
+        match self {
+            Self::Greyscale | Self::Indexed => 1,
+            Self::GreyscaleAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+
+    /// Returns `true` if the bit depth is allowed for the colour type.
+    fn allows_bit_depth(self, bit_depth: u8) -> bool {
+        match self {
+            Self::Greyscale => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            Self::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+            Self::Rgb | Self::GreyscaleAlpha | Self::Rgba => matches!(bit_depth, 8 | 16),
+        }
+    }
+}
+
+/// The image properties stored in the `IHDR` chunk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Header {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+
+    /// Number of bits per sample, or per palette index for indexed-colour images.
+    pub(crate) bit_depth: u8,
+
+    pub(crate) colour_type: ColourType,
+
+    /// Whether the image data is stored using Adam7 interlacing.
+    pub(crate) interlaced: bool,
+}
+
+impl Header {
+    /// Parses the data of an `IHDR` chunk.
+    pub(crate) fn from_chunk(chunk: &Chunk) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::InvalidImage(message.to_string());
+
+        if chunk.chunk_type().to_string() != "IHDR" {
+            return Err(invalid("the first chunk is not an IHDR chunk"));
+        }
+
+        let data: &[u8; 13] = chunk
+            .data()
+            .try_into()
+            .map_err(|_| invalid("the IHDR chunk is not 13 bytes long"))?;
+        let [w0, w1, w2, w3, h0, h1, h2, h3, bit_depth, colour_type, compression_method, filter_method, interlace_method] =
+            *data;
+
+        let width = u32::from_be_bytes([w0, w1, w2, w3]);
+        let height = u32::from_be_bytes([h0, h1, h2, h3]);
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(invalid("the image dimensions are out of range"));
+        }
+
+        let colour_type =
+            ColourType::from_byte(colour_type).ok_or_else(|| invalid("unknown colour type"))?;
+        if !colour_type.allows_bit_depth(bit_depth) {
+            return Err(invalid("the bit depth is not allowed for the colour type"));
+        }
+
+        if compression_method != 0 || filter_method != 0 || interlace_method > 1 {
+            return Err(invalid("unknown compression, filter or interlace method"));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            colour_type,
+            interlaced: interlace_method == 1,
+        })
+    }
+
+    /// Builds an `IHDR` chunk from the header, the reverse of [`from_chunk`](Header::from_chunk).
+    pub(crate) fn to_chunk(self) -> Chunk {
+        let mut data = Vec::with_capacity(13);
+        data.extend(self.width.to_be_bytes());
+        data.extend(self.height.to_be_bytes());
+        data.extend([
+            self.bit_depth,
+            self.colour_type.to_byte(),
+            0,
+            0,
+            u8::from(self.interlaced),
+        ]);
+
+        Chunk::new(
+            ChunkType::try_from(*b"IHDR").expect("IHDR is a valid chunk type"),
+            data,
+        )
+    }
+
+    /// Returns the number of bits in each pixel.
+    pub(crate) fn bits_per_pixel(&self) -> usize {
+        self.colour_type.channels() * usize::from(self.bit_depth)
+    }
+
+    /// Returns the number of bytes in a scanline of the given width, excluding the filter type
+    /// byte, or `None` if it overflows.
+    pub(crate) fn row_bytes(&self, width: usize) -> Option<usize> {
+        width
+            .checked_mul(self.bits_per_pixel())
+            .map(|bits| bits.div_ceil(8))
+    }
+
+    /// Returns the length of the decompressed image data, including the filter type byte of each
+    /// scanline. Fails if the image has more than [`MAX_SAMPLES`](MAX_SAMPLES) samples.
+    pub(crate) fn data_length(&self) -> Result<usize, Error> {
+        let too_large = || Error::InvalidImage("the image dimensions are too large".to_string());
+
+        let samples = (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|pixels| pixels.checked_mul(self.colour_type.channels()));
+        if !matches!(samples, Some(samples) if samples <= MAX_SAMPLES) {
+            return Err(too_large());
+        }
+
+        self.passes()
+            .iter()
+            .filter(|pass| !pass.is_empty())
+            .try_fold(0_usize, |length, pass| {
+                let scanline = self.row_bytes(pass.width)?.checked_add(1)?;
+                length.checked_add(pass.height.checked_mul(scanline)?)
+            })
+            .ok_or_else(too_large)
+    }
+
+    /// Returns the number of bytes in each pixel, rounded up to one, which filters use to find
+    /// the corresponding byte of the previous pixel.
+    pub(crate) fn filter_bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    /// Returns the passes the image data is stored in: seven for an interlaced image, or a single
+    /// one covering the whole image otherwise.
+    pub(crate) fn passes(&self) -> Vec<Pass> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if !self.interlaced {
+            return vec![Pass {
+                x: 0,
+                y: 0,
+                dx: 1,
+                dy: 1,
+                width,
+                height,
+            }];
+        }
+
+        ADAM7_PASSES
+            .iter()
+            .map(|&(x, y, dx, dy)| Pass {
+                x,
+                y,
+                dx,
+                dy,
+                width: width.saturating_sub(x).div_ceil(dx),
+                height: height.saturating_sub(y).div_ceil(dy),
+            })
+            .collect()
+    }
+
+    /// Returns the number of scanlines in the image data, each of which starts with a filter type
+    /// byte.
+    pub(crate) fn scanlines(&self) -> usize {
+        self.passes()
+            .iter()
+            .filter(|pass| !pass.is_empty())
+            .map(|pass| pass.height)
+            .sum()
+    }
+}
+
+/// A pass over the image, consisting of every `dx`-th pixel of every `dy`-th row starting from
+/// pixel `(x, y)`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Pass {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) dx: usize,
+    pub(crate) dy: usize,
+
+    /// Number of pixels in each row of the pass.
+    pub(crate) width: usize,
+
+    /// Number of rows in the pass.
+    pub(crate) height: usize,
+}
+
+impl Pass {
+    /// Returns `true` if the pass contains no pixels, in which case it is left out of the image
+    /// data entirely.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// The filter applied to a scanline before compression, to make it compress better.
+///
+/// For more information, check the [PNG Filter Algorithms] page.
+///
+/// [PNG Filter Algorithms]: http://www.libpng.org/pub/png/spec/1.2/PNG-Filters.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl FilterType {
+    pub(crate) const ALL: [Self; 5] = [Self::None, Self::Sub, Self::Up, Self::Average, Self::Paeth];
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::None),
+            1 => Some(Self::Sub),
+            2 => Some(Self::Up),
+            3 => Some(Self::Average),
+            4 => Some(Self::Paeth),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Sub => 1,
+            Self::Up => 2,
+            Self::Average => 3,
+            Self::Paeth => 4,
+        }
+    }
+}
+
+/// How to choose the filter applied to each scanline when encoding an image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum FilterStrategy {
+    /// Apply the same filter to every scanline.
+    Fixed(FilterType),
+
+    /// Apply whichever filter minimizes the sum of the absolute values of the filtered bytes,
+    /// treated as signed, which tends to compress best. Indexed-colour images and images with a
+    /// bit depth below 8 are left unfiltered, as filters rarely help them.
+    Adaptive,
+}
+
+/// Applies a filter to a scanline, given the previous unfiltered scanline of the same pass (all
+/// zeros for the first one) and the number of bytes per pixel.
+pub(crate) fn filter(filter_type: FilterType, row: &[u8], previous: &[u8], bpp: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let (left, up_left) = if i >= bpp {
+                (row[i - bpp], previous[i - bpp])
+            } else {
+                (0, 0)
+            };
+            let up = previous[i];
+
+            let prediction = match filter_type {
+                FilterType::None => 0,
+                FilterType::Sub => left,
+                FilterType::Up => up,
+                FilterType::Average => u8::midpoint(left, up),
+                FilterType::Paeth => paeth_predictor(left, up, up_left),
+            };
+            row[i].wrapping_sub(prediction)
+        })
+        .collect()
+}
+
+/// Reverses the filter applied to a scanline in place, given the previous unfiltered scanline of
+/// the same pass (all zeros for the first one) and the number of bytes per pixel.
+pub(crate) fn unfilter(filter_type: FilterType, row: &mut [u8], previous: &[u8], bpp: usize) {
+    match filter_type {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        FilterType::Up => {
+            for (byte, &up) in row.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(up);
+            }
+        }
+        FilterType::Average => {
+            for i in 0..row.len() {
+                let left = if i >= bpp { row[i - bpp] } else { 0 };
+                row[i] = row[i].wrapping_add(u8::midpoint(left, previous[i]));
+            }
+        }
+        FilterType::Paeth => {
+            for i in 0..row.len() {
+                let (left, up_left) = if i >= bpp {
+                    (row[i - bpp], previous[i - bpp])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth_predictor(left, previous[i], up_left));
+            }
+        }
+    }
+}
+
+/// Returns whichever of the left, up and upper left bytes is closest to `left + up - up_left`.
+pub(crate) fn paeth_predictor(left: u8, up: u8, up_left: u8) -> u8 {
+    let (a, b, c) = (i16::from(left), i16::from(up), i16::from(up_left));
+    let p = a + b - c;
+    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Packs samples of the given bit depth into a scanline, the reverse of
+/// [`unpack_samples`](unpack_samples). The last byte is padded with zero bits if needed.
+fn pack_samples(samples: &[u16], bit_depth: u8) -> Vec<u8> {
+    match bit_depth {
+        16 => samples
+            .iter()
+            .flat_map(|sample| sample.to_be_bytes())
+            .collect(),
+        #[allow(clippy::cast_possible_truncation)]
+        8 => samples.iter().map(|&sample| sample as u8).collect(),
+        _ => {
+            let per_byte = usize::from(8 / bit_depth);
+            samples
+                .chunks(per_byte)
+                .map(|chunk| {
+                    chunk.iter().enumerate().fold(0_u8, |byte, (i, &sample)| {
+                        #[allow(clippy::cast_possible_truncation)]
+                        let shift = (per_byte - 1 - i) as u8 * bit_depth;
+                        #[allow(clippy::cast_possible_truncation)]
+                        let sample = sample as u8;
+                        byte | (sample << shift)
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+/// Splits an unfiltered scanline into samples of the given bit depth. Samples smaller than a byte
+/// are packed starting from the most significant bit, and 16-bit samples are big-endian.
+fn unpack_samples(row: &[u8], bit_depth: u8) -> Vec<u16> {
+    match bit_depth {
+        16 => row
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect(),
+        8 => row.iter().copied().map(u16::from).collect(),
+        _ => {
+            let mask = (1_u8 << bit_depth) - 1;
+            let per_byte = 8 / bit_depth;
+            row.iter()
+                .flat_map(|&byte| {
+                    (0..per_byte)
+                        .rev()
+                        .map(move |i| u16::from((byte >> (i * bit_depth)) & mask))
+                })
+                .collect()
+        }
+    }
+}
+
+/// Samples which should be treated as fully transparent, or the alpha values of palette entries,
+/// as stored in the `tRNS` chunk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Transparency {
+    /// The greyscale sample value of transparent pixels.
+    Greyscale(u16),
+
+    /// The R, G, B sample values of transparent pixels.
+    Rgb([u16; 3]),
+
+    /// The alpha values of the first palette entries. Entries beyond the end are fully opaque.
+    Palette(Vec<u8>),
+}
+
+/// An image decoded from the `IDAT` chunks of a PNG file.
+///
+/// Samples are stored unpacked in row-major order, one [`u16`](u16) per sample, at the bit depth
+/// of the image. Pixels of indexed-colour images consist of a single sample, the palette index.
+#[derive(Clone, Debug)]
+pub(crate) struct Image {
+    header: Header,
+    samples: Vec<u16>,
+    palette: Vec<[u8; 3]>,
+    transparency: Option<Transparency>,
+}
+
+impl Image {
+    /// Creates an image from its header and samples, without a palette or transparency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of samples doesn't match the dimensions and colour type of the header.
+    pub(crate) fn new(header: Header, samples: Vec<u16>) -> Self {
+        assert_eq!(
+            samples.len(),
+            header.width as usize * header.height as usize * header.colour_type.channels()
+        );
+
+        Self {
+            header,
+            samples,
+            palette: Vec::new(),
+            transparency: None,
+        }
+    }
+
+    /// Creates a non-interlaced image of the given dimensions, colour type and bit depth from its
+    /// samples, for tests.
+    #[cfg(test)]
+    pub(crate) fn from_samples(
+        width: u32,
+        height: u32,
+        colour_type: ColourType,
+        bit_depth: u8,
+        samples: Vec<u16>,
+    ) -> Self {
+        let header = Header {
+            width,
+            height,
+            bit_depth,
+            colour_type,
+            interlaced: false,
+        };
+
+        Self::new(header, samples)
+    }
+
+    /// Decodes the image data of the PNG.
+    pub(crate) fn decode(png: &Png) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::InvalidImage(message.to_string());
+
+        let header = Header::from_chunk(
+            png.chunks()
+                .first()
+                .ok_or_else(|| invalid("the PNG file has no chunks"))?,
+        )?;
+        let palette: Vec<[u8; 3]> = png
+            .chunk_by_type("PLTE")
+            .map(|chunk| {
+                chunk
+                    .data()
+                    .chunks_exact(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+                    .collect()
+            })
+            .unwrap_or_default();
+        if header.colour_type == ColourType::Indexed && palette.is_empty() {
+            return Err(invalid("the PLTE chunk is missing"));
+        }
+
+        let transparency = png
+            .chunk_by_type("tRNS")
+            .and_then(|chunk| transparency(header.colour_type, chunk.data()));
+
+        let (_, data) = decompress_image_data(png)?;
+        let samples = decode_samples(&header, &data)?;
+
+        if header.colour_type == ColourType::Indexed
+            && samples
+                .iter()
+                .any(|&index| usize::from(index) >= palette.len())
+        {
+            return Err(invalid("a palette index is out of range"));
+        }
+
+        Ok(Self {
+            header,
+            samples,
+            palette,
+            transparency,
+        })
+    }
+
+    pub(crate) fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.header.width as usize
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.header.height as usize
+    }
+
+    /// Returns the number of samples in each pixel.
+    pub(crate) fn channels(&self) -> usize {
+        self.header.colour_type.channels()
+    }
+
+    /// Returns all samples of the image in row-major order.
+    pub(crate) fn samples(&self) -> &[u16] {
+        &self.samples
+    }
+
+    /// Returns all samples of the image in row-major order for modification. Samples must stay
+    /// within the range allowed by the bit depth of the image.
+    pub(crate) fn samples_mut(&mut self) -> &mut [u16] {
+        &mut self.samples
+    }
+
+    /// Returns the samples of the pixel at the given position.
+    pub(crate) fn pixel(&self, x: usize, y: usize) -> &[u16] {
+        let start = (y * self.width() + x) * self.channels();
+        &self.samples[start..start + self.channels()]
+    }
+
+    /// Returns the sample of the given channel of the pixel at the given position.
+    #[cfg(test)]
+    pub(crate) fn sample(&self, x: usize, y: usize, channel: usize) -> u16 {
+        self.pixel(x, y)[channel]
+    }
+
+    /// Returns the palette of an indexed-colour image, or an empty palette otherwise.
+    pub(crate) fn palette(&self) -> &[[u8; 3]] {
+        &self.palette
+    }
+
+    pub(crate) fn transparency(&self) -> Option<&Transparency> {
+        self.transparency.as_ref()
+    }
+
+    /// Returns the colour of the pixel at the given position as R, G, B and alpha values scaled
+    /// to 16 bits, regardless of the colour type and bit depth of the image.
+    pub(crate) fn rgba(&self, x: usize, y: usize) -> [u16; 4] {
+        let scale = |sample: u16| scale_to_16_bits(sample, self.header.bit_depth);
+        let pixel = self.pixel(x, y);
+
+        match (self.header.colour_type, &self.transparency) {
+            (ColourType::Greyscale, transparency) => {
+                let alpha = match transparency {
+                    Some(Transparency::Greyscale(grey)) if *grey == pixel[0] => 0,
+                    _ => u16::MAX,
+                };
+                [scale(pixel[0]), scale(pixel[0]), scale(pixel[0]), alpha]
+            }
+            (ColourType::Rgb, transparency) => {
+                let alpha = match transparency {
+                    Some(Transparency::Rgb(rgb)) if rgb[..] == pixel[..3] => 0,
+                    _ => u16::MAX,
+                };
+                [scale(pixel[0]), scale(pixel[1]), scale(pixel[2]), alpha]
+            }
+            (ColourType::Indexed, transparency) => {
+                let index = usize::from(pixel[0]);
+                let [red, green, blue] = self.palette[index];
+                let alpha = match transparency {
+                    Some(Transparency::Palette(alpha)) => alpha.get(index).copied().unwrap_or(255),
+                    _ => 255,
+                };
+                [red, green, blue, alpha].map(|value| u16::from(value) * 257)
+            }
+            (ColourType::GreyscaleAlpha, _) => [
+                scale(pixel[0]),
+                scale(pixel[0]),
+                scale(pixel[0]),
+                scale(pixel[1]),
+            ],
+            (ColourType::Rgba, _) => [
+                scale(pixel[0]),
+                scale(pixel[1]),
+                scale(pixel[2]),
+                scale(pixel[3]),
+            ],
+        }
+    }
+}
+
+impl Image {
+    /// Filters and compresses the samples of the image into image data for `IDAT` chunks, using
+    /// the bit depth, colour type and interlacing of the image's header and the given zlib
+    /// compression level (0-9).
+    pub(crate) fn encode(&self, filter_strategy: FilterStrategy, level: u32) -> Vec<u8> {
+        let header = &self.header;
+        let filter_strategy = match filter_strategy {
+            FilterStrategy::Adaptive
+                if header.colour_type == ColourType::Indexed || header.bit_depth < 8 =>
+            {
+                FilterStrategy::Fixed(FilterType::None)
+            }
+            filter_strategy => filter_strategy,
+        };
+
+        self.encode_scanlines(level, |_, scanline, previous, bpp| match filter_strategy {
+            FilterStrategy::Fixed(filter_type) => {
+                (filter_type, filter(filter_type, scanline, previous, bpp))
+            }
+            FilterStrategy::Adaptive => FilterType::ALL
+                .iter()
+                .map(|&filter_type| (filter_type, filter(filter_type, scanline, previous, bpp)))
+                .min_by_key(|(_, filtered)| {
+                    filtered
+                        .iter()
+                        .map(|&byte| u64::from(byte.cast_signed().unsigned_abs()))
+                        .sum::<u64>()
+                })
+                .expect("There is always at least one filter type"),
+        })
+    }
+
+    /// Like [`encode`](Self::encode), but applies the given filter to each scanline, in the order
+    /// they appear in the image data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of filter types doesn't match the number of scanlines.
+    pub(crate) fn encode_with_filters(&self, filter_types: &[FilterType], level: u32) -> Vec<u8> {
+        assert_eq!(filter_types.len(), self.header.scanlines());
+
+        self.encode_scanlines(level, |index, scanline, previous, bpp| {
+            let filter_type = filter_types[index];
+            (filter_type, filter(filter_type, scanline, previous, bpp))
+        })
+    }
+
+    /// Filters and compresses the samples of the image, calling `filter_scanline` with the index,
+    /// the unfiltered bytes, the previous unfiltered scanline and the bytes per pixel of each
+    /// scanline to choose its filter type and filter it.
+    fn encode_scanlines(
+        &self,
+        level: u32,
+        mut filter_scanline: impl FnMut(usize, &[u8], &[u8], usize) -> (FilterType, Vec<u8>),
+    ) -> Vec<u8> {
+        let header = &self.header;
+        let (width, channels) = (self.width(), self.channels());
+        let bpp = header.filter_bytes_per_pixel();
+
+        let mut data = Vec::new();
+        let mut index = 0;
+        for pass in header.passes().iter().filter(|pass| !pass.is_empty()) {
+            let row_bytes = header
+                .row_bytes(pass.width)
+                .expect("The scanlines of an image in memory fit in memory");
+            let mut previous = vec![0_u8; row_bytes];
+
+            for row in 0..pass.height {
+                let y = pass.y + row * pass.dy;
+                let samples: Vec<u16> = (0..pass.width)
+                    .flat_map(|column| {
+                        let start = (y * width + pass.x + column * pass.dx) * channels;
+                        self.samples[start..start + channels].iter().copied()
+                    })
+                    .collect();
+                let scanline = pack_samples(&samples, header.bit_depth);
+
+                let (filter_type, filtered) = filter_scanline(index, &scanline, &previous, bpp);
+                data.push(filter_type.to_byte());
+                data.extend(filtered);
+                previous = scanline;
+                index += 1;
+            }
+        }
+
+        zlib::compress(&data, level)
+    }
+}
+
+/// Returns the header and the decompressed image data of the PNG. Fails if the image data
+/// decompresses to more than the header allows.
+pub(crate) fn decompress_image_data(png: &Png) -> Result<(Header, Vec<u8>), Error> {
+    let header = Header::from_chunk(
+        png.chunks()
+            .first()
+            .ok_or_else(|| Error::InvalidImage("the PNG file has no chunks".to_string()))?,
+    )?;
+    let data = zlib::decompress(&png.image_data(), header.data_length()?)?;
+
+    Ok((header, data))
+}
+
+/// Returns the filter type of each scanline in the image data of the PNG, in the order they
+/// appear.
+pub(crate) fn filter_types(png: &Png) -> Result<Vec<FilterType>, Error> {
+    let (header, data) = decompress_image_data(png)?;
+
+    let mut filter_types = Vec::with_capacity(header.scanlines());
+    let mut offset = 0;
+    for pass in header.passes().iter().filter(|pass| !pass.is_empty()) {
+        let row_bytes = header
+            .row_bytes(pass.width)
+            .expect("The length of the image data was checked");
+        for _ in 0..pass.height {
+            let byte = *data.get(offset).ok_or_else(|| {
+                Error::InvalidImage(format!(
+                    "expected at least {} bytes of image data, found {}",
+                    offset + 1,
+                    data.len()
+                ))
+            })?;
+            let filter_type = FilterType::from_byte(byte)
+                .ok_or_else(|| Error::InvalidImage(format!("unknown filter type {byte}")))?;
+
+            filter_types.push(filter_type);
+            offset += 1 + row_bytes;
+        }
+    }
+
+    Ok(filter_types)
+}
+
+/// Parses the data of a `tRNS` chunk. Returns `None` if the colour type doesn't allow one or the
+/// data is too short.
+fn transparency(colour_type: ColourType, data: &[u8]) -> Option<Transparency> {
+    let sample = |i: usize| Some(u16::from_be_bytes([*data.get(i)?, *data.get(i + 1)?]));
+
+    match colour_type {
+        ColourType::Greyscale => Some(Transparency::Greyscale(sample(0)?)),
+        ColourType::Rgb => Some(Transparency::Rgb([sample(0)?, sample(2)?, sample(4)?])),
+        ColourType::Indexed => Some(Transparency::Palette(data.to_vec())),
+        ColourType::GreyscaleAlpha | ColourType::Rgba => None,
+    }
+}
+
+/// Unfilters the decompressed image data and unpacks it into samples, placing the pixels of each
+/// interlacing pass at their positions in the image.
+fn decode_samples(header: &Header, data: &[u8]) -> Result<Vec<u16>, Error> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let channels = header.colour_type.channels();
+    let bpp = header.filter_bytes_per_pixel();
+
+    // Check the length up front to avoid allocating samples for truncated or bogus image data
+    let expected_length = header.data_length()?;
+    if data.len() < expected_length {
+        return Err(Error::InvalidImage(format!(
+            "expected {expected_length} bytes of image data, found {}",
+            data.len()
+        )));
+    }
+
+    let mut samples = vec![0_u16; width * height * channels];
+    let mut offset = 0;
+    for pass in header.passes().iter().filter(|pass| !pass.is_empty()) {
+        let row_bytes = header
+            .row_bytes(pass.width)
+            .expect("The length of the image data was checked");
+        let mut previous = vec![0_u8; row_bytes];
+
+        for row in 0..pass.height {
+            let filter_type = FilterType::from_byte(data[offset]).ok_or_else(|| {
+                Error::InvalidImage(format!("unknown filter type {}", data[offset]))
+            })?;
+            let mut scanline = data[offset + 1..offset + 1 + row_bytes].to_vec();
+            unfilter(filter_type, &mut scanline, &previous, bpp);
+
+            let y = pass.y + row * pass.dy;
+            let row_samples = unpack_samples(&scanline, header.bit_depth);
+            for (column, pixel) in row_samples
+                .chunks_exact(channels)
+                .take(pass.width)
+                .enumerate()
+            {
+                let x = pass.x + column * pass.dx;
+                let start = (y * width + x) * channels;
+                samples[start..start + channels].copy_from_slice(pixel);
+            }
+
+            previous = scanline;
+            offset += 1 + row_bytes;
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Scales a sample of the given bit depth to 16 bits.
+fn scale_to_16_bits(sample: u16, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => sample,
+        8 => sample * 257,
+        _ => {
+            let max = (1_u32 << bit_depth) - 1;
+            #[allow(clippy::cast_possible_truncation)]
+            let scaled = (u32::from(sample) * 65535 / max) as u16;
+            scaled
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    /// Builds a PNG from the IHDR fields, the filtered image data and any additional chunks.
+    fn testing_png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        colour_type: u8,
+        interlaced: bool,
+        image_data: &[u8],
+        chunks: &[Chunk],
+    ) -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([bit_depth, colour_type, 0, 0, u8::from(interlaced)]);
+
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend(chunk("IHDR", &ihdr).as_bytes());
+        for extra in chunks {
+            bytes.extend(extra.as_bytes());
+        }
+        bytes.extend(chunk("IDAT", &zlib::compress(image_data, 9)).as_bytes());
+        bytes.extend(chunk("IEND", &[]).as_bytes());
+
+        Png::try_from(bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_unfilter() {
+        let previous = [10, 20, 30, 40];
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Sub, &mut row, &previous, 1);
+        assert_eq!(row, [1, 3, 6, 10]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Up, &mut row, &previous, 1);
+        assert_eq!(row, [11, 22, 33, 44]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Average, &mut row, &previous, 1);
+        assert_eq!(row, [6, 15, 25, 36]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Paeth, &mut row, &previous, 1);
+        assert_eq!(row, [11, 22, 33, 44]);
+    }
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(paeth_predictor(10, 20, 10), 20);
+        assert_eq!(paeth_predictor(20, 10, 10), 20);
+        assert_eq!(paeth_predictor(10, 20, 15), 15);
+        assert_eq!(paeth_predictor(10, 20, 30), 10);
+    }
+
+    #[test]
+    fn test_decode_rgb() {
+        #[rustfmt::skip]
+        let image_data = [
+            1, 10, 20, 30, 5, 5, 5,     // Sub
+            2, 1, 1, 1, 1, 1, 1,        // Up
+        ];
+        let png = testing_png(2, 2, 8, 2, false, &image_data, &[]);
+        let image = Image::decode(&png).unwrap();
+
+        assert_eq!(image.pixel(0, 0), [10, 20, 30]);
+        assert_eq!(image.pixel(1, 0), [15, 25, 35]);
+        assert_eq!(image.pixel(0, 1), [11, 21, 31]);
+        assert_eq!(image.sample(1, 1, 2), 36);
+        assert_eq!(image.rgba(1, 1), [16 * 257, 26 * 257, 36 * 257, u16::MAX]);
+    }
+
+    #[test]
+    fn test_decode_low_bit_depth() {
+        // 1-bit greyscale, 10 pixels per row, padded to 2 bytes
+        let image_data = [0, 0b1010_0000, 0b1100_0000];
+        let png = testing_png(10, 1, 1, 0, false, &image_data, &[]);
+        let image = Image::decode(&png).unwrap();
+
+        assert_eq!(image.samples(), [1_u16, 0, 1, 0, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(image.rgba(0, 0), [u16::MAX, u16::MAX, u16::MAX, u16::MAX]);
+        assert_eq!(image.rgba(1, 0), [0, 0, 0, u16::MAX]);
+    }
+
+    #[test]
+    fn test_decode_16_bit() {
+        let image_data = [0, 0x12, 0x34, 0xff, 0xff];
+        let png = testing_png(1, 1, 16, 4, false, &image_data, &[]);
+        let image = Image::decode(&png).unwrap();
+
+        assert_eq!(image.pixel(0, 0), [0x1234, 0xffff]);
+        assert_eq!(image.rgba(0, 0), [0x1234, 0x1234, 0x1234, 0xffff]);
+    }
+
+    #[test]
+    fn test_decode_palette_with_transparency() {
+        // 2-bit palette indices
+        let image_data = [0, 0b0001_1000];
+        let chunks = [
+            chunk("PLTE", &[255, 0, 0, 0, 255, 0, 0, 0, 255]),
+            chunk("tRNS", &[128]),
+        ];
+        let png = testing_png(3, 1, 2, 3, false, &image_data, &chunks);
+        let image = Image::decode(&png).unwrap();
+
+        assert_eq!(image.samples(), [0, 1, 2]);
+        assert_eq!(image.palette().len(), 3);
+        assert_eq!(
+            image.transparency(),
+            Some(&Transparency::Palette(vec![128]))
+        );
+        assert_eq!(image.rgba(0, 0), [u16::MAX, 0, 0, 128 * 257]);
+        assert_eq!(image.rgba(2, 0), [0, 0, u16::MAX, u16::MAX]);
+    }
+
+    #[test]
+    fn test_decode_greyscale_transparency() {
+        let image_data = [0, 7, 8];
+        let chunks = [chunk("tRNS", &[0, 7])];
+        let png = testing_png(2, 1, 8, 0, false, &image_data, &chunks);
+        let image = Image::decode(&png).unwrap();
+
+        assert_eq!(image.rgba(0, 0)[3], 0);
+        assert_eq!(image.rgba(1, 0)[3], u16::MAX);
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        // A 3x3 greyscale image, where each pixel's value is its position
+        #[rustfmt::skip]
+        let image_data = [
+            0, 0,       // Pass 1: (0, 0)
+            0, 2,       // Pass 4: (2, 0)
+            0, 6, 8,    // Pass 5: (0, 2), (2, 2)
+            0, 1,       // Pass 6: (1, 0)
+            0, 7,       //         (1, 2)
+            0, 3, 4, 5, // Pass 7: (0, 1), (1, 1), (2, 1)
+        ];
+        let png = testing_png(3, 3, 8, 0, true, &image_data, &[]);
+        let image = Image::decode(&png).unwrap();
+
+        assert_eq!(image.samples(), (0..9).collect::<Vec<u16>>());
+    }
+
+    #[test]
+    fn test_pack_samples() {
+        for (bit_depth, row) in [(1, vec![0b1010_0000, 0b1100_0000]), (4, vec![0x12, 0x30])] {
+            let samples = unpack_samples(&row, bit_depth);
+            assert_eq!(pack_samples(&samples, bit_depth), row);
+        }
+
+        assert_eq!(pack_samples(&[0x1234], 16), [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let previous = [10, 20, 30, 40, 50, 60];
+        let row = [200, 3, 70, 255, 0, 90];
+
+        for filter_type in FilterType::ALL {
+            let mut filtered = filter(filter_type, &row, &previous, 2);
+            unfilter(filter_type, &mut filtered, &previous, 2);
+            assert_eq!(filtered, row);
+        }
+    }
+
+    /// Builds an image of the given format, filled with a pattern of samples.
+    fn testing_image(bit_depth: u8, colour_type: u8, width: u32, height: u32) -> Image {
+        let header = Header {
+            width,
+            height,
+            bit_depth,
+            colour_type: ColourType::from_byte(colour_type).unwrap(),
+            interlaced: false,
+        };
+        let samples_per_row = width as usize * header.colour_type.channels();
+        let modulus = if colour_type == 3 { 16 } else { 1 << bit_depth };
+
+        let mut image_data = Vec::new();
+        for row in 0..height as usize {
+            let samples: Vec<u16> = (0..samples_per_row)
+                .map(|i| u16::try_from(((row * samples_per_row + i) * 37 + 11) % modulus).unwrap())
+                .collect();
+            image_data.push(0);
+            image_data.extend(pack_samples(&samples, bit_depth));
+        }
+
+        let chunks = [chunk("PLTE", &[0; 48])];
+        let png = testing_png(
+            width,
+            height,
+            bit_depth,
+            colour_type,
+            false,
+            &image_data,
+            &chunks,
+        );
+        Image::decode(&png).unwrap()
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let strategies = FilterType::ALL
+            .map(FilterStrategy::Fixed)
+            .into_iter()
+            .chain([FilterStrategy::Adaptive]);
+
+        for filter_strategy in strategies {
+            for (bit_depth, colour_type) in [(1, 0), (4, 3), (8, 0), (16, 0), (8, 2), (16, 6)] {
+                for interlaced in [false, true] {
+                    let mut image = testing_image(bit_depth, colour_type, 11, 9);
+                    image.header.interlaced = interlaced;
+
+                    let chunks = [chunk("PLTE", &[0; 48])];
+                    let mut png =
+                        testing_png(11, 9, bit_depth, colour_type, interlaced, &[], &chunks);
+                    png.replace_image_data(&image.encode(filter_strategy, 6), 8);
+
+                    let decoded = Image::decode(&png).unwrap();
+                    assert_eq!(decoded.samples(), image.samples());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_with_filters() {
+        let mut image = testing_image(8, 2, 11, 9);
+        image.header.interlaced = true;
+        let scanlines = image.header.scanlines();
+        assert_eq!(scanlines, 2 + 2 + 1 + 3 + 2 + 5 + 4);
+
+        let chosen: Vec<FilterType> = (0..scanlines)
+            .map(|index| FilterType::ALL[index % 5])
+            .collect();
+        let mut png = testing_png(11, 9, 8, 2, true, &[], &[]);
+        png.replace_image_data(&image.encode_with_filters(&chosen, 6), 8192);
+
+        assert_eq!(filter_types(&png).unwrap(), chosen);
+        assert_eq!(Image::decode(&png).unwrap().samples(), image.samples());
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = Header {
+            width: 640,
+            height: 480,
+            bit_depth: 16,
+            colour_type: ColourType::Rgba,
+            interlaced: true,
+        };
+
+        let chunk = header.to_chunk();
+        assert_eq!(chunk.chunk_type().to_string(), "IHDR");
+        assert_eq!(Header::from_chunk(&chunk).unwrap(), header);
+    }
+
+    #[test]
+    fn test_decode_invalid_image() {
+        let png = testing_png(2, 2, 8, 2, false, &[0, 1, 2, 3], &[]);
+        assert!(matches!(Image::decode(&png), Err(Error::InvalidImage(_))));
+
+        let png = testing_png(1, 1, 8, 0, false, &[5, 0], &[]);
+        assert!(matches!(Image::decode(&png), Err(Error::InvalidImage(_))));
+
+        let png = testing_png(1, 1, 8, 3, false, &[0, 0], &[]);
+        assert!(matches!(Image::decode(&png), Err(Error::InvalidImage(_))));
+    }
+
+    #[test]
+    fn test_decode_huge_image() {
+        let max = i32::MAX as u32;
+        for (width, height, colour_type, interlaced) in [
+            (max, max, 6, false),
+            (max, max, 6, true),
+            (max, 1, 6, false),
+            (1 << 15, 1 << 15, 2, false),
+        ] {
+            let png = testing_png(width, height, 16, colour_type, interlaced, &[0; 16], &[]);
+            assert!(matches!(Image::decode(&png), Err(Error::InvalidImage(_))));
+            assert!(matches!(filter_types(&png), Err(Error::InvalidImage(_))));
+        }
+    }
+
+    #[test]
+    fn test_decode_too_much_image_data() {
+        let png = testing_png(1, 1, 8, 0, false, &[0, 1, 2, 3], &[]);
+        assert!(matches!(
+            Image::decode(&png),
+            Err(Error::DecompressionFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::crypto;
+use crate::error::Error;
+use crate::image::{ColourType, Image};
+
+/// Separates the seed of the sample permutation from other keys derived from the passphrase.
+const SEED_PURPOSE: &str = "pngme lsb sample order";
+
+/// Number of bytes used to store the length of the payload in front of it.
+const LENGTH_PREFIX_LENGTH: usize = 4;
+
+/// Which pixels of an image may carry payload bits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Selection {
+    /// Skip pixels whose colour matches all of their neighbours, ignoring the two least
+    /// significant bits of each sample. Changes in flat regions are easier to spot.
+    pub(crate) skip_flat: bool,
+
+    /// Skip pixels with an alpha of zero. Their colour is never displayed, which makes them
+    /// suspicious when it isn't uniform.
+    pub(crate) skip_transparent: bool,
+}
+
+/// Returns the number of payload bytes which can be hidden in the image.
+pub(crate) fn capacity(image: &Image, selection: Selection) -> Result<usize, Error> {
+    let bits = candidates(image, selection)?.len();
+
+    Ok((bits / 8).saturating_sub(LENGTH_PREFIX_LENGTH))
+}
+
+/// Hides the payload in the least significant bits of the samples of the image.
+///
+/// The payload is prefixed with its length as a 4-byte big-endian integer. Its bits are scattered
+/// across the selected samples in an order determined by `passphrase`, and are embedded by adding
+/// or subtracting one from samples whose least significant bit doesn't match (LSB matching),
+/// which leaves fewer statistical traces than overwriting the bit.
+pub(crate) fn embed(
+    image: &mut Image,
+    payload: &[u8],
+    passphrase: &str,
+    selection: Selection,
+) -> Result<(), Error> {
+    let candidates = candidates(image, selection)?;
+    let capacity = (candidates.len() / 8).saturating_sub(LENGTH_PREFIX_LENGTH);
+    let length = u32::try_from(payload.len())
+        .ok()
+        .filter(|_| payload.len() <= capacity)
+        .ok_or(Error::PayloadTooLarge {
+            length: payload.len(),
+            capacity,
+        })?;
+
+    let bytes = length
+        .to_be_bytes()
+        .into_iter()
+        .chain(payload.iter().copied());
+    let order = keyed_order(candidates, crypto::derive_seed(passphrase, SEED_PURPOSE));
+    let samples = image.samples_mut();
+    let mut rng = rand::thread_rng();
+
+    for (bit, index) in bytes.flat_map(bits).zip(order) {
+        let sample = &mut samples[index];
+        if *sample & 1 != u16::from(bit) {
+            *sample = step(*sample, rng.gen());
+        }
+    }
+
+    Ok(())
+}
+
+/// Recovers a payload hidden by [`embed`](embed) with the same passphrase and selection.
+pub(crate) fn extract(
+    image: &Image,
+    passphrase: &str,
+    selection: Selection,
+) -> Result<Vec<u8>, Error> {
+    let candidates = candidates(image, selection)?;
+    let capacity = (candidates.len() / 8).saturating_sub(LENGTH_PREFIX_LENGTH);
+    let samples = image.samples();
+    let mut order = keyed_order(candidates, crypto::derive_seed(passphrase, SEED_PURPOSE));
+    let mut read_bytes = |count: usize| -> Vec<u8> {
+        (0..count)
+            .map(|_| {
+                order
+                    .by_ref()
+                    .take(8)
+                    .fold(0_u8, |byte, index| (byte << 1) | (samples[index] & 1) as u8)
+            })
+            .collect()
+    };
+
+    let length_bytes: [u8; LENGTH_PREFIX_LENGTH] = read_bytes(LENGTH_PREFIX_LENGTH)
+        .try_into()
+        .expect("Exactly as many bytes as requested are read");
+    let length = u32::from_be_bytes(length_bytes) as usize;
+    if length > capacity {
+        return Err(Error::PayloadNotFound);
+    }
+
+    Ok(read_bytes(length))
+}
+
+/// Returns the indices of the samples which may carry payload bits, in row-major order.
+///
+/// Only colour samples are used, never alpha samples, and embedding never changes a sample beyond
+/// its two least significant bits, so the same samples are selected after embedding.
+fn candidates(image: &Image, selection: Selection) -> Result<Vec<usize>, Error> {
+    let header = image.header();
+    let (colour_channels, alpha_channel) = match header.colour_type {
+        ColourType::Greyscale => (1, None),
+        ColourType::Rgb => (3, None),
+        ColourType::GreyscaleAlpha => (1, Some(1)),
+        ColourType::Rgba => (3, Some(3)),
+        ColourType::Indexed => {
+            return Err(Error::UnsupportedImage(
+                "indexed-colour images can't carry bits in their samples".to_string(),
+            ))
+        }
+    };
+    if header.bit_depth < 8 {
+        return Err(Error::UnsupportedImage(format!(
+            "images with a bit depth of {} can't carry bits in their samples",
+            header.bit_depth
+        )));
+    }
+
+    let (width, height, channels) = (image.width(), image.height(), image.channels());
+    let coarse = |x: usize, y: usize| image.pixel(x, y)[..colour_channels].iter().map(|s| s >> 2);
+    let is_flat = |x: usize, y: usize| {
+        let neighbours = [
+            (x.checked_sub(1), Some(y)),
+            (Some(x + 1).filter(|&x| x < width), Some(y)),
+            (Some(x), y.checked_sub(1)),
+            (Some(x), Some(y + 1).filter(|&y| y < height)),
+        ];
+        neighbours.into_iter().all(|neighbour| match neighbour {
+            (Some(nx), Some(ny)) => coarse(x, y).eq(coarse(nx, ny)),
+            _ => true,
+        })
+    };
+
+    let mut candidates = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let transparent = alpha_channel.is_some_and(|alpha| image.pixel(x, y)[alpha] == 0);
+            if (selection.skip_transparent && transparent) || (selection.skip_flat && is_flat(x, y))
+            {
+                continue;
+            }
+
+            let start = (y * width + x) * channels;
+            candidates.extend(start..start + colour_channels);
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Yields the candidates in a pseudo-random order determined by the seed, using a Fisher-Yates
+/// shuffle that is carried out lazily, so that the first candidates yielded don't depend on how
+/// many are taken.
+fn keyed_order(mut candidates: Vec<usize>, seed: [u8; 32]) -> impl Iterator<Item = usize> {
+    let mut rng = ChaCha20Rng::from_seed(seed);
+
+    (0..candidates.len()).map(move |i| {
+        let j = rng.gen_range(i..candidates.len());
+        candidates.swap(i, j);
+        candidates[i]
+    })
+}
+
+/// Returns the bits of the byte, most significant bit first.
+fn bits(byte: u8) -> impl Iterator<Item = u8> {
+    (0..8).rev().map(move |shift| (byte >> shift) & 1)
+}
+
+/// Adds or subtracts one from the sample, flipping its least significant bit without changing
+/// any of the other bits but the second least significant one. `up` chooses the direction when
+/// both are possible.
+fn step(sample: u16, up: bool) -> u16 {
+    match sample % 4 {
+        0 => sample + 1,
+        3 => sample - 1,
+        _ if up => sample + 1,
+        _ => sample - 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Header;
+
+    fn testing_image(colour_type: ColourType, width: u32, height: u32) -> Image {
+        let header = Header {
+            width,
+            height,
+            bit_depth: 8,
+            colour_type,
+            interlaced: false,
+        };
+        let length = width as usize * height as usize * colour_type.channels();
+        let samples = (0..length)
+            .map(|i| u16::try_from((i * 37 + 11) % 256).unwrap())
+            .collect();
+
+        Image::new(header, samples)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut image = testing_image(ColourType::Rgb, 16, 16);
+        let original = image.samples().to_vec();
+
+        embed(
+            &mut image,
+            b"hidden message",
+            "passphrase",
+            Selection::default(),
+        )
+        .unwrap();
+        assert_ne!(image.samples(), original);
+        assert!(image
+            .samples()
+            .iter()
+            .zip(&original)
+            .all(|(&new, &old)| new.abs_diff(old) <= 1 && new >> 2 == old >> 2));
+
+        let payload = extract(&image, "passphrase", Selection::default()).unwrap();
+        assert_eq!(payload, b"hidden message");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let mut image = testing_image(ColourType::Rgb, 16, 16);
+        embed(
+            &mut image,
+            b"hidden message",
+            "passphrase",
+            Selection::default(),
+        )
+        .unwrap();
+
+        let payload = extract(&image, "wrong passphrase", Selection::default());
+        assert!(payload.map_or(true, |payload| payload != b"hidden message"));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let image = testing_image(ColourType::Rgb, 16, 16);
+        assert_eq!(
+            capacity(&image, Selection::default()).unwrap(),
+            16 * 16 * 3 / 8 - 4
+        );
+
+        let mut image = testing_image(ColourType::Rgb, 4, 4);
+        let result = embed(&mut image, &[0; 3], "passphrase", Selection::default());
+        assert!(matches!(
+            result,
+            Err(Error::PayloadTooLarge {
+                length: 3,
+                capacity: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_skip_transparent() {
+        let mut image = testing_image(ColourType::Rgba, 16, 16);
+        for pixel in image.samples_mut().chunks_exact_mut(4).step_by(2) {
+            pixel[3] = 0;
+        }
+        let original = image.samples().to_vec();
+        let selection = Selection {
+            skip_transparent: true,
+            ..Selection::default()
+        };
+
+        assert_eq!(capacity(&image, selection).unwrap(), 8 * 16 * 3 / 8 - 4);
+        embed(&mut image, b"hidden message", "passphrase", selection).unwrap();
+        for (new, old) in image
+            .samples()
+            .chunks_exact(4)
+            .zip(original.chunks_exact(4))
+        {
+            assert_eq!(new[3], old[3]);
+            if old[3] == 0 {
+                assert_eq!(new, old);
+            }
+        }
+
+        let payload = extract(&image, "passphrase", selection).unwrap();
+        assert_eq!(payload, b"hidden message");
+    }
+
+    #[test]
+    fn test_skip_flat() {
+        let mut image = testing_image(ColourType::Greyscale, 16, 16);
+        for sample in &mut image.samples_mut()[..16 * 8] {
+            *sample = 100;
+        }
+        let selection = Selection {
+            skip_flat: true,
+            ..Selection::default()
+        };
+
+        // The last row of the uniform top half borders the bottom half, so it isn't flat
+        assert_eq!(capacity(&image, selection).unwrap(), 16 * 9 / 8 - 4);
+
+        let mut image = testing_image(ColourType::Greyscale, 32, 32);
+        let original = image.samples().to_vec();
+        embed(&mut image, b"hidden", "passphrase", selection).unwrap();
+        assert_ne!(image.samples(), original);
+        assert_eq!(extract(&image, "passphrase", selection).unwrap(), b"hidden");
+    }
+
+    #[test]
+    fn test_unsupported_image() {
+        let image = testing_image(ColourType::Indexed, 4, 4);
+        assert!(matches!(
+            capacity(&image, Selection::default()),
+            Err(Error::UnsupportedImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_keyed_order() {
+        let order: Vec<usize> = keyed_order((0..100).collect(), [1; 32]).collect();
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        assert_ne!(order, sorted);
+
+        let prefix: Vec<usize> = keyed_order((0..100).collect(), [1; 32]).take(10).collect();
+        assert_eq!(prefix, order[..10]);
+        let other: Vec<usize> = keyed_order((0..100).collect(), [2; 32]).collect();
+        assert_ne!(order, other);
+    }
+
+    #[test]
+    fn test_step() {
+        assert_eq!(step(0, false), 1);
+        assert_eq!(step(255, true), 254);
+        assert_eq!(step(65535, true), 65534);
+        assert_eq!(step(5, true), 6);
+        assert_eq!(step(6, false), 5);
+    }
+}
@@ -2,12 +2,29 @@
 
 use log::LevelFilter;
 
+mod analyze;
 mod args;
+mod batch;
+mod bitplane;
+mod carve;
 mod chunk;
 mod chunk_type;
 mod commands;
+mod compare;
+mod create;
+mod crypto;
+mod diff;
+mod envelope;
 mod error;
+mod filter;
+mod idat;
+mod image;
+mod lsb;
+mod manifest;
+mod palette;
 mod png;
+mod text;
+mod zlib;
 
 use args::{Opts, SubCommand};
 
@@ -24,11 +41,31 @@ fn main() {
     builder.init();
 
     if let Err(error) = match opts.subcommand {
-        SubCommand::Encode(args) => commands::encode(args),
-        SubCommand::Decode(args) => commands::decode(args),
-        SubCommand::Remove(args) => commands::remove(args),
-        SubCommand::Print(args) => commands::print(args),
+        SubCommand::Encode(args) => commands::encode(&args),
+        SubCommand::Decode(args) => commands::decode(&args),
+        SubCommand::Remove(args) => commands::remove(&args),
+        SubCommand::Print(args) => commands::print(&args),
+        SubCommand::Strip(args) => commands::strip(&args),
+        SubCommand::Scan(args) => commands::scan(&args),
+        SubCommand::Carve(args) => commands::carve(&args),
+        SubCommand::Extract(args) => commands::extract(&args),
+        SubCommand::Import(args) => commands::import(&args),
+        SubCommand::Diff(args) => commands::diff(&args),
+        SubCommand::ComparePixels(args) => commands::compare_pixels(&args),
+        SubCommand::Textconv(args) => commands::textconv(&args),
+        SubCommand::ChunkType(args) => commands::chunk_type(&args),
+        SubCommand::Reencode(args) => commands::reencode(&args),
+        SubCommand::Hide(args) => commands::hide(&args),
+        SubCommand::Reveal(args) => commands::reveal(&args),
+        SubCommand::Capacity(args) => commands::capacity(&args),
+        SubCommand::Analyze(args) => commands::analyze(&args),
+        SubCommand::Bitplane(args) => commands::bitplane(&args),
+        SubCommand::Create(args) => commands::create(&args),
     } {
-        log::error!("{}", error);
+        log::error!("{} [{}]", error, error.code());
+        if let Some(hint) = error.hint() {
+            log::error!("hint: {}", hint);
+        }
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+
+/// Describes the chunks written to a directory by the `extract` subcommand.
+#[derive(Debug, Serialize)]
+pub(crate) struct ExtractManifest {
+    /// Path to the PNG file the chunks were extracted from.
+    pub(crate) file: String,
+
+    pub(crate) chunks: Vec<ExtractedChunk>,
+}
+
+/// A chunk extracted from a PNG file.
+#[derive(Debug, Serialize)]
+pub(crate) struct ExtractedChunk {
+    #[serde(rename = "type")]
+    pub(crate) chunk_type: String,
+
+    /// Position of the chunk in the list of chunks of the PNG file, starting from zero.
+    pub(crate) index: usize,
+
+    /// Byte offset of the chunk within the PNG file.
+    pub(crate) offset: usize,
+
+    /// Number of bytes in the chunk's data field.
+    pub(crate) length: u32,
+
+    pub(crate) crc: u32,
+
+    pub(crate) payload: Payload,
+}
+
+/// The data extracted from a chunk.
+#[derive(Debug, Serialize)]
+pub(crate) struct Payload {
+    /// Path to the file containing the payload, relative to the manifest.
+    pub(crate) path: String,
+
+    /// Number of bytes in the payload, after decompression.
+    pub(crate) length: usize,
+
+    pub(crate) encoding: PayloadEncoding,
+
+    /// Whether the payload is stored compressed in the chunk.
+    pub(crate) compressed: bool,
+
+    /// Whether the payload is stored encrypted in the chunk. If the payload could be decrypted,
+    /// the decrypted payload is saved.
+    pub(crate) encrypted: bool,
+
+    /// The keyword of a textual chunk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) keyword: Option<String>,
+
+    /// The language tag of an `iTXt` chunk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) language_tag: Option<String>,
+
+    /// The translated keyword of an `iTXt` chunk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) translated_keyword: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum PayloadEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    Binary,
+}
+
+/// Describes the chunks to add to a PNG file using the `import` subcommand.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ImportManifest {
+    #[serde(default, rename = "chunk")]
+    pub(crate) chunks: Vec<ImportedChunk>,
+}
+
+/// A chunk to add to a PNG file. Exactly one of `text`, `file` and `env` must be specified as the
+/// source of the chunk's data.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ImportedChunk {
+    #[serde(rename = "type")]
+    pub(crate) chunk_type: String,
+
+    /// Text to store in the chunk.
+    pub(crate) text: Option<String>,
+
+    /// Path to a file whose contents to store in the chunk, relative to the manifest.
+    pub(crate) file: Option<String>,
+
+    /// Name of an environment variable whose value to store in the chunk.
+    pub(crate) env: Option<String>,
+
+    #[serde(default)]
+    pub(crate) placement: Placement,
+
+    /// Whether to compress the data before storing it in the chunk.
+    #[serde(default)]
+    pub(crate) compress: bool,
+
+    /// Whether to encrypt the data before storing it in the chunk.
+    #[serde(default)]
+    pub(crate) encrypt: bool,
+}
+
+/// Where to insert a chunk in a PNG file.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Placement {
+    /// Right after the `IHDR` chunk.
+    Start,
+
+    /// Right before the first `IDAT` chunk.
+    BeforeData,
+
+    /// Right after the last `IDAT` chunk.
+    AfterData,
+
+    /// Right before the `IEND` chunk.
+    #[default]
+    End,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_manifest() -> ExtractManifest {
+        ExtractManifest {
+            file: "image.png".to_string(),
+            chunks: vec![ExtractedChunk {
+                chunk_type: "ruSt".to_string(),
+                index: 2,
+                offset: 57,
+                length: 5,
+                crc: 0x1234_5678,
+                payload: Payload {
+                    path: "002-ruSt.txt".to_string(),
+                    length: 5,
+                    encoding: PayloadEncoding::Utf8,
+                    compressed: false,
+                    encrypted: false,
+                    keyword: None,
+                    language_tag: None,
+                    translated_keyword: Some("Titel".to_string()),
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn test_extract_manifest_json() {
+        let json = serde_json::to_value(testing_manifest()).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "file": "image.png",
+                "chunks": [{
+                    "type": "ruSt",
+                    "index": 2,
+                    "offset": 57,
+                    "length": 5,
+                    "crc": 0x1234_5678,
+                    "payload": {
+                        "path": "002-ruSt.txt",
+                        "length": 5,
+                        "encoding": "utf-8",
+                        "compressed": false,
+                        "encrypted": false,
+                        "translated_keyword": "Titel",
+                    },
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_extract_manifest_toml() {
+        let toml = toml::to_string_pretty(&testing_manifest()).unwrap();
+
+        assert_eq!(
+            toml,
+            r#"file = "image.png"
+
+[[chunks]]
+type = "ruSt"
+index = 2
+offset = 57
+length = 5
+crc = 305419896
+
+[chunks.payload]
+path = "002-ruSt.txt"
+length = 5
+encoding = "utf-8"
+compressed = false
+encrypted = false
+translated_keyword = "Titel"
+"#
+        );
+    }
+
+    #[test]
+    fn test_import_manifest() {
+        let manifest: ImportManifest = toml::from_str(
+            r#"
+[[chunk]]
+type = "ruSt"
+text = "This is a secret message!"
+
+[[chunk]]
+type = "fiLe"
+file = "message.bin"
+placement = "before-data"
+compress = true
+encrypt = true
+"#,
+        )
+        .unwrap();
+
+        let [text, file] = &manifest.chunks[..] else {
+            panic!("expected two chunks, found {:?}", manifest.chunks);
+        };
+        assert_eq!(text.chunk_type, "ruSt");
+        assert_eq!(text.text.as_deref(), Some("This is a secret message!"));
+        assert_eq!(text.placement, Placement::End);
+        assert!(!text.compress && !text.encrypt);
+        assert_eq!(file.chunk_type, "fiLe");
+        assert_eq!(file.file.as_deref(), Some("message.bin"));
+        assert_eq!(file.placement, Placement::BeforeData);
+        assert!(file.compress && file.encrypt);
+
+        let empty: ImportManifest = toml::from_str("").unwrap();
+        assert!(empty.chunks.is_empty());
+    }
+
+    #[test]
+    fn test_import_manifest_invalid() {
+        for manifest in [
+            "[[chunk]]\ntext = \"no type\"",
+            "[[chunk]]\ntype = \"ruSt\"\ntext = \"hi\"\nunknown = 1",
+            "[[chunk]]\ntype = \"ruSt\"\ntext = \"hi\"\nplacement = \"middle\"",
+            "[[chunks]]\ntype = \"ruSt\"\ntext = \"hi\"",
+        ] {
+            assert!(
+                toml::from_str::<ImportManifest>(manifest).is_err(),
+                "{manifest}"
+            );
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+
+use rand::seq::SliceRandom;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto;
+use crate::error::Error;
+use crate::image::{ColourType, Image, Transparency};
+use crate::png::Png;
+
+/// Separates the seed of the reference order of palette entries from other keys derived from the
+/// passphrase.
+const SEED_PURPOSE: &str = "pngme palette order";
+
+/// Number of bytes used to store the length of the payload in front of it. Palettes of at most
+/// 256 entries can't hold more than 210 bytes.
+const LENGTH_PREFIX_LENGTH: usize = 2;
+
+/// The colour and alpha of a palette entry.
+type Entry = [u8; 4];
+
+/// Returns the number of payload bytes which can be hidden in the order of the palette entries of
+/// the image. Duplicate entries are merged when hiding a payload, so they don't count.
+pub(crate) fn capacity(image: &Image) -> Result<usize, Error> {
+    let (entries, _) = distinct_entries(image)?;
+
+    Ok(number_length(entries.len()).saturating_sub(LENGTH_PREFIX_LENGTH))
+}
+
+/// Hides the payload in the order of the palette entries of an indexed-colour image, leaving the
+/// rendered image unchanged.
+///
+/// The payload, prefixed with its length as a 2-byte big-endian integer and padded with random
+/// bytes, is read as a number, which selects one of the possible orders of the palette entries
+/// relative to a reference order determined by `passphrase`. Duplicate entries are merged first,
+/// since swapping them couldn't be told apart. The `PLTE`, `tRNS`, `bKGD` and `hIST` chunks of
+/// the PNG are rewritten and the samples of the image remapped to the new order, so the image data
+/// must be encoded again afterwards.
+pub(crate) fn embed(
+    png: &mut Png,
+    image: &mut Image,
+    payload: &[u8],
+    passphrase: &str,
+) -> Result<(), Error> {
+    let (entries, old_to_distinct) = distinct_entries(image)?;
+    let length = number_length(entries.len());
+    let capacity = length.saturating_sub(LENGTH_PREFIX_LENGTH);
+    // Small palettes can't even hold the length prefix
+    let prefix = u16::try_from(payload.len())
+        .ok()
+        .filter(|_| length >= LENGTH_PREFIX_LENGTH && payload.len() <= capacity)
+        .ok_or(Error::PayloadTooLarge {
+            length: payload.len(),
+            capacity,
+        })?;
+
+    let mut number = vec![0_u8; length];
+    number[..LENGTH_PREFIX_LENGTH].copy_from_slice(&prefix.to_be_bytes());
+    number[LENGTH_PREFIX_LENGTH..LENGTH_PREFIX_LENGTH + payload.len()].copy_from_slice(payload);
+    rand::thread_rng().fill_bytes(&mut number[LENGTH_PREFIX_LENGTH + payload.len()..]);
+
+    // Pick the entry at each position from the remaining entries of the reference order, using
+    // the digits of the number in the factorial number system
+    let mut remaining = reference_order(&entries, passphrase);
+    let mut order = Vec::with_capacity(entries.len());
+    for radix in (1..=entries.len()).rev() {
+        let digit = div_rem(&mut number, radix);
+        order.push(remaining.remove(digit));
+    }
+
+    let mut distinct_to_new = vec![0_u8; entries.len()];
+    for (new_index, &distinct_index) in order.iter().enumerate() {
+        distinct_to_new[distinct_index] = index_to_u8(new_index);
+    }
+    let old_to_new: Vec<u8> = old_to_distinct
+        .iter()
+        .map(|&distinct_index| distinct_to_new[distinct_index])
+        .collect();
+
+    for sample in image.samples_mut() {
+        *sample = u16::from(old_to_new[usize::from(*sample)]);
+    }
+    rewrite_chunks(
+        png,
+        &order.iter().map(|&i| entries[i]).collect::<Vec<_>>(),
+        &old_to_new,
+    );
+
+    Ok(())
+}
+
+/// Recovers a payload hidden by [`embed`](embed) with the same passphrase.
+pub(crate) fn extract(image: &Image, passphrase: &str) -> Result<Vec<u8>, Error> {
+    let (entries, old_to_distinct) = distinct_entries(image)?;
+    if old_to_distinct.len() != entries.len() {
+        // Palettes with hidden payloads have no duplicate entries
+        return Err(Error::PayloadNotFound);
+    }
+
+    let mut remaining = reference_order(&entries, passphrase);
+    let mut digits = Vec::with_capacity(entries.len());
+    for distinct_index in 0..entries.len() {
+        let digit = remaining
+            .iter()
+            .position(|&index| index == distinct_index)
+            .expect("Every entry is in the reference order exactly once");
+        digits.push(digit);
+        remaining.remove(digit);
+    }
+
+    let mut number = vec![0_u8; number_length(entries.len())];
+    if number.len() < LENGTH_PREFIX_LENGTH {
+        return Err(Error::PayloadNotFound);
+    }
+    for (position, digit) in digits.into_iter().enumerate().rev() {
+        if !mul_add(&mut number, entries.len() - position, digit) {
+            return Err(Error::PayloadNotFound);
+        }
+    }
+
+    let (prefix, rest) = number.split_at(LENGTH_PREFIX_LENGTH);
+    let length = usize::from(u16::from_be_bytes([prefix[0], prefix[1]]));
+    if length > rest.len() {
+        return Err(Error::PayloadNotFound);
+    }
+
+    Ok(rest[..length].to_vec())
+}
+
+/// Returns the distinct entries of the palette of the image in order of first occurrence, and
+/// the index of the distinct entry for each palette index.
+fn distinct_entries(image: &Image) -> Result<(Vec<Entry>, Vec<usize>), Error> {
+    if image.header().colour_type != ColourType::Indexed {
+        return Err(Error::UnsupportedImage(
+            "only indexed-colour images have a palette".to_string(),
+        ));
+    }
+
+    let alpha = match image.transparency() {
+        Some(Transparency::Palette(alpha)) => alpha.as_slice(),
+        _ => &[],
+    };
+    let mut entries = Vec::new();
+    let mut positions = HashMap::new();
+    let old_to_distinct = image
+        .palette()
+        .iter()
+        .enumerate()
+        .map(|(index, &[red, green, blue])| {
+            let entry = [red, green, blue, alpha.get(index).copied().unwrap_or(255)];
+            *positions.entry(entry).or_insert_with(|| {
+                entries.push(entry);
+                entries.len() - 1
+            })
+        })
+        .collect();
+
+    Ok((entries, old_to_distinct))
+}
+
+/// Returns the indices of the entries in an order which depends only on the set of entries and
+/// the passphrase, not on their current order.
+fn reference_order(entries: &[Entry], passphrase: &str) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_unstable_by_key(|&index| entries[index]);
+    order.shuffle(&mut ChaCha20Rng::from_seed(crypto::derive_seed(
+        passphrase,
+        SEED_PURPOSE,
+    )));
+
+    order
+}
+
+/// Returns the number of bytes of the largest number that always selects a valid order of the
+/// given number of entries, that is, the largest `n` such that `256^n <= entries!`.
+fn number_length(entries: usize) -> usize {
+    #[allow(clippy::cast_precision_loss)]
+    let bits: f64 = (2..=entries).map(|k| (k as f64).log2()).sum();
+
+    // Round down generously so that floating point errors never overestimate the length
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let length = ((bits - 1e-6) / 8.0).floor().max(0.0) as usize;
+    length
+}
+
+/// Divides the big-endian number in place by `divisor`, returning the remainder.
+fn div_rem(number: &mut [u8], divisor: usize) -> usize {
+    let mut remainder = 0;
+    for byte in number {
+        let value = (remainder << 8) | usize::from(*byte);
+        *byte = index_to_u8(value / divisor);
+        remainder = value % divisor;
+    }
+
+    remainder
+}
+
+/// Multiplies the big-endian number in place by `factor` and adds `addend`. Returns `false` if
+/// the result doesn't fit.
+fn mul_add(number: &mut [u8], factor: usize, addend: usize) -> bool {
+    let mut carry = addend;
+    for byte in number.iter_mut().rev() {
+        let value = usize::from(*byte) * factor + carry;
+        *byte = value.to_le_bytes()[0];
+        carry = value >> 8;
+    }
+
+    carry == 0
+}
+
+fn index_to_u8(index: usize) -> u8 {
+    u8::try_from(index).expect("Palettes have at most 256 entries")
+}
+
+/// Rewrites the chunks which depend on the palette for the new palette entries, given the new
+/// index of each old palette index.
+fn rewrite_chunks(png: &mut Png, palette: &[Entry], old_to_new: &[u8]) {
+    let chunk_type = |name: &[u8; 4]| ChunkType::try_from(*name).expect("Valid chunk type");
+
+    png.replace_chunk(Chunk::new(
+        chunk_type(b"PLTE"),
+        palette
+            .iter()
+            .flat_map(|entry| entry[..3].to_vec())
+            .collect(),
+    ));
+
+    // Trailing fully opaque entries can be left out of the tRNS chunk
+    let alpha: Vec<u8> = palette.iter().map(|entry| entry[3]).collect();
+    let alpha_length = alpha
+        .iter()
+        .rposition(|&alpha| alpha != 255)
+        .map_or(0, |i| i + 1);
+    if alpha_length == 0 {
+        let _ = png.remove_chunk("tRNS");
+    } else {
+        png.replace_chunk(Chunk::new(
+            chunk_type(b"tRNS"),
+            alpha[..alpha_length].to_vec(),
+        ));
+    }
+
+    if let Some(&[index]) = png.chunk_by_type("bKGD").map(Chunk::data) {
+        if let Some(&new_index) = old_to_new.get(usize::from(index)) {
+            png.replace_chunk(Chunk::new(chunk_type(b"bKGD"), vec![new_index]));
+        }
+    }
+
+    if let Some(histogram) = png.chunk_by_type("hIST").map(Chunk::data) {
+        let mut frequencies = vec![0_u16; palette.len()];
+        for (old_index, frequency) in histogram.chunks_exact(2).enumerate() {
+            if let Some(&new_index) = old_to_new.get(old_index) {
+                let frequency = u16::from_be_bytes([frequency[0], frequency[1]]);
+                let total = &mut frequencies[usize::from(new_index)];
+                *total = total.saturating_add(frequency);
+            }
+        }
+        png.replace_chunk(Chunk::new(
+            chunk_type(b"hIST"),
+            frequencies
+                .iter()
+                .flat_map(|frequency| frequency.to_be_bytes())
+                .collect(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{FilterStrategy, Header};
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    /// Builds an indexed-colour PNG with the given palette, alpha values and extra chunks, and
+    /// returns it along with its decoded image.
+    fn testing_png(palette: &[[u8; 3]], alpha: &[u8], extra: Vec<Chunk>) -> (Png, Image) {
+        let header = Header {
+            width: 16,
+            height: 16,
+            bit_depth: 8,
+            colour_type: ColourType::Indexed,
+            interlaced: false,
+        };
+        let samples = (0..256_u16)
+            .map(|i| (i * 7) % u16::try_from(palette.len()).unwrap())
+            .collect();
+
+        let mut chunks = vec![
+            header.to_chunk(),
+            chunk("PLTE", palette.iter().flatten().copied().collect()),
+        ];
+        if !alpha.is_empty() {
+            chunks.push(chunk("tRNS", alpha.to_vec()));
+        }
+        chunks.extend(extra);
+        chunks.push(chunk("IEND", Vec::new()));
+
+        let mut png = Png::from_chunks(chunks);
+        let image_data = Image::new(header, samples).encode(FilterStrategy::Adaptive, 9);
+        png.replace_image_data(&image_data, 8192);
+        let image = Image::decode(&png).unwrap();
+
+        (png, image)
+    }
+
+    fn testing_palette(length: u16) -> Vec<[u8; 3]> {
+        (0..length)
+            .map(|i| {
+                let i = u8::try_from(i).unwrap();
+                [i, 255 - i, i / 2]
+            })
+            .collect()
+    }
+
+    /// Hides the payload and returns the re-encoded PNG, decoded again.
+    fn hide(png: &mut Png, image: &mut Image, payload: &[u8]) -> Image {
+        embed(png, image, payload, "passphrase").unwrap();
+        png.replace_image_data(&image.encode(FilterStrategy::Adaptive, 9), 8192);
+
+        Image::decode(png).unwrap()
+    }
+
+    fn rendered(image: &Image) -> Vec<[u16; 4]> {
+        (0..image.height())
+            .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+            .map(|(x, y)| image.rgba(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (mut png, mut image) = testing_png(&testing_palette(64), &[0, 128], vec![]);
+        let original = rendered(&image);
+
+        let hidden = hide(&mut png, &mut image, b"hidden message");
+        assert_eq!(rendered(&hidden), original);
+        assert_ne!(hidden.palette(), testing_palette(64));
+        assert_eq!(extract(&hidden, "passphrase").unwrap(), b"hidden message");
+
+        let payload = extract(&hidden, "wrong passphrase");
+        assert!(payload.map_or(true, |payload| payload != b"hidden message"));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let (_, image) = testing_png(&testing_palette(256), &[], vec![]);
+        assert_eq!(capacity(&image).unwrap(), 210 - LENGTH_PREFIX_LENGTH);
+
+        let (mut png, mut image) = testing_png(&testing_palette(8), &[], vec![]);
+        assert_eq!(capacity(&image).unwrap(), 0);
+        assert!(matches!(
+            embed(&mut png, &mut image, b"x", "passphrase"),
+            Err(Error::PayloadTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_small_palette() {
+        for length in [1, 4, 6] {
+            let (mut png, mut image) = testing_png(&testing_palette(length), &[], vec![]);
+            assert!(number_length(usize::from(length)) < LENGTH_PREFIX_LENGTH);
+            assert!(matches!(
+                embed(&mut png, &mut image, b"", "passphrase"),
+                Err(Error::PayloadTooLarge { .. })
+            ));
+            assert!(matches!(
+                extract(&image, "passphrase"),
+                Err(Error::PayloadNotFound)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_duplicate_entries() {
+        let mut palette = testing_palette(32);
+        palette[5] = palette[1];
+        let (mut png, mut image) = testing_png(&palette, &[], vec![]);
+        let original = rendered(&image);
+        assert!(extract(&image, "passphrase").is_err());
+
+        let hidden = hide(&mut png, &mut image, b"hidden");
+        assert_eq!(hidden.palette().len(), 31);
+        assert_eq!(rendered(&hidden), original);
+        assert_eq!(extract(&hidden, "passphrase").unwrap(), b"hidden");
+    }
+
+    #[test]
+    fn test_dependent_chunks() {
+        let histogram = (0..32_u16).flat_map(u16::to_be_bytes).collect();
+        let (mut png, mut image) = testing_png(
+            &testing_palette(32),
+            &[255, 255, 10],
+            vec![chunk("bKGD", vec![2]), chunk("hIST", histogram)],
+        );
+
+        let hidden = hide(&mut png, &mut image, b"hidden");
+        let background = usize::from(png.chunk_by_type("bKGD").unwrap().data()[0]);
+        assert_eq!(hidden.palette()[background], testing_palette(32)[2]);
+
+        let alpha = png.chunk_by_type("tRNS").unwrap().data();
+        assert_eq!(alpha[background], 10);
+        assert_eq!(alpha.iter().filter(|&&alpha| alpha != 255).count(), 1);
+
+        let histogram = png.chunk_by_type("hIST").unwrap().data();
+        assert_eq!(histogram[background * 2..background * 2 + 2], [0, 2]);
+    }
+
+    #[test]
+    fn test_unsupported_image() {
+        let header = Header {
+            width: 1,
+            height: 1,
+            bit_depth: 8,
+            colour_type: ColourType::Rgb,
+            interlaced: false,
+        };
+        let image = Image::new(header, vec![0; 3]);
+        assert!(matches!(capacity(&image), Err(Error::UnsupportedImage(_))));
+    }
+
+    #[test]
+    fn test_number_length() {
+        assert_eq!(number_length(0), 0);
+        assert_eq!(number_length(5), 0);
+        // 6! = 720 >= 256
+        assert_eq!(number_length(6), 1);
+        assert_eq!(number_length(256), 210);
+    }
+
+    #[test]
+    fn test_div_rem_and_mul_add() {
+        let mut number = [0x01, 0x00];
+        assert_eq!(div_rem(&mut number, 3), 1);
+        assert_eq!(number, [0, 85]);
+        assert!(mul_add(&mut number, 3, 1));
+        assert_eq!(number, [0x01, 0x00]);
+        assert!(!mul_add(&mut number, 256, 0));
+    }
+}
@@ -2,13 +2,20 @@ use crate::{chunk::Chunk, chunk_type::ChunkType, error::Error};
 
 pub(crate) struct Png {
     chunks: Vec<Chunk>,
+
+    /// Any bytes found after the `IEND` chunk. Decoders ignore them, which makes them a common
+    /// place to hide data.
+    trailing_data: Vec<u8>,
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub(crate) const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Png { chunks }
+    pub(crate) fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        Png {
+            chunks,
+            trailing_data: Vec::new(),
+        }
     }
 
     pub(crate) fn append_chunk(&mut self, chunk: Chunk) -> Result<(), Error> {
@@ -25,6 +32,31 @@ impl Png {
         }
     }
 
+    /// Inserts the chunk at the given position in the list of chunks.
+    pub(crate) fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Replaces the first chunk of the same type as `chunk`, returning the replaced chunk. Returns
+    /// `None` without adding the chunk if there is no chunk of that type.
+    pub(crate) fn replace_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        let existing = self
+            .chunks
+            .iter_mut()
+            .find(|existing| existing.chunk_type() == chunk.chunk_type())?;
+
+        Some(std::mem::replace(existing, chunk))
+    }
+
+    /// Replaces the chunk at the given position, returning the replaced chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub(crate) fn replace_chunk_at(&mut self, index: usize, chunk: Chunk) -> Chunk {
+        std::mem::replace(&mut self.chunks[index], chunk)
+    }
+
     pub(crate) fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, Error> {
         use std::str::FromStr;
 
@@ -39,6 +71,20 @@ impl Png {
         }
     }
 
+    /// Removes every chunk for which `predicate` returns `true`, returning the removed chunks in
+    /// their original order.
+    pub(crate) fn remove_chunks(
+        &mut self,
+        mut predicate: impl FnMut(&Chunk) -> bool,
+    ) -> Vec<Chunk> {
+        let (removed, kept) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| predicate(chunk));
+        self.chunks = kept;
+
+        removed
+    }
+
     pub(crate) fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
@@ -49,20 +95,58 @@ impl Png {
             .find(|&chunk| chunk.chunk_type().to_string() == chunk_type)
     }
 
-    pub(crate) fn as_bytes(&self) -> Vec<u8> {
+    /// Returns the concatenated data of all `IDAT` chunks, which together form the compressed
+    /// image data.
+    pub(crate) fn image_data(&self) -> Vec<u8> {
         self.chunks
             .iter()
-            .fold(Png::STANDARD_HEADER.to_vec(), |mut bytes, chunk| {
-                bytes.append(&mut chunk.as_bytes());
-                bytes
-            })
+            .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect()
     }
-}
 
-impl std::convert::TryFrom<&[u8]> for Png {
-    type Error = Error;
+    /// Replaces all `IDAT` chunks with new ones holding the given image data, split into chunks
+    /// of at most `chunk_size` bytes. The new chunks take the place of the first `IDAT` chunk, and
+    /// all other chunks are kept in place.
+    pub(crate) fn replace_image_data(&mut self, image_data: &[u8], chunk_size: usize) {
+        self.replace_idat_chunks(image_data.chunks(chunk_size.max(1)));
+    }
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+    /// Replaces all `IDAT` chunks with one new chunk for each piece of image data, in order. The
+    /// new chunks take the place of the first `IDAT` chunk, and all other chunks are kept in
+    /// place. A single empty `IDAT` chunk is added if there are no pieces.
+    pub(crate) fn replace_idat_chunks<'a>(&mut self, pieces: impl IntoIterator<Item = &'a [u8]>) {
+        use std::str::FromStr;
+
+        let is_idat = |chunk: &Chunk| chunk.chunk_type().to_string() == "IDAT";
+        let index = self
+            .chunks
+            .iter()
+            .position(is_idat)
+            .or_else(|| {
+                self.chunks
+                    .iter()
+                    .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+            })
+            .unwrap_or(self.chunks.len());
+        self.chunks.retain(|chunk| !is_idat(chunk));
+
+        let chunk_type = ChunkType::from_str("IDAT").expect("IDAT is a valid chunk type");
+        let mut idat_chunks: Vec<Chunk> = pieces
+            .into_iter()
+            .map(|data| Chunk::new(chunk_type.clone(), data.to_vec()))
+            .collect();
+        if idat_chunks.is_empty() {
+            idat_chunks.push(Chunk::new(chunk_type, Vec::new()));
+        }
+
+        self.chunks.splice(index..index, idat_chunks);
+    }
+
+    /// Parses a PNG from the start of `value`, stopping after the `IEND` chunk.
+    /// Returns the PNG and the number of bytes it spans, including the header. Any bytes after the
+    /// `IEND` chunk are ignored.
+    pub(crate) fn parse_prefix(value: &[u8]) -> Result<(Png, usize), Error> {
         if value.len() < 8 {
             return Err(Error::InvalidPngFileSize(value.len()));
         }
@@ -76,18 +160,68 @@ impl std::convert::TryFrom<&[u8]> for Png {
         let value = &value[8..];
         let mut chunks = Vec::new();
         while ptr < value.len() {
-            let length = u32::from_be_bytes(value[ptr..ptr + 4].try_into()?) as usize;
+            let remaining = &value[ptr..];
+            let length = match remaining {
+                [a, b, c, d, ..] => u32::from_be_bytes([*a, *b, *c, *d]) as usize,
+                _ => 0,
+            };
             let offset = 4 + // 4 bytes for the chunk length
                 4 + // 4 bytes for the chunk type
                 length + // `length` bytes for the chunk data
                 4; // 4 bytes for the chunk CRC
 
-            let chunk = Chunk::try_from(&value[ptr..(ptr + offset)])?;
+            let chunk =
+                Chunk::try_from(&remaining[..offset.min(remaining.len())]).map_err(|error| {
+                    Error::InChunk {
+                        index: chunks.len(),
+                        offset: 8 + ptr,
+                        source: Box::new(error),
+                    }
+                })?;
+            let is_iend = chunk.chunk_type().to_string() == "IEND";
             chunks.push(chunk);
             ptr += offset;
+
+            if is_iend {
+                break;
+            }
         }
 
-        Ok(Png::from_chunks(chunks))
+        Ok((Png::from_chunks(chunks), 8 + ptr))
+    }
+
+    /// Returns the bytes found after the `IEND` chunk, if any.
+    pub(crate) fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+
+    /// Removes and returns the bytes found after the `IEND` chunk.
+    pub(crate) fn remove_trailing_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailing_data)
+    }
+
+    pub(crate) fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            self.chunks
+                .iter()
+                .fold(Png::STANDARD_HEADER.to_vec(), |mut bytes, chunk| {
+                    bytes.append(&mut chunk.as_bytes());
+                    bytes
+                });
+        bytes.extend_from_slice(&self.trailing_data);
+
+        bytes
+    }
+}
+
+impl std::convert::TryFrom<&[u8]> for Png {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let (mut png, length) = Png::parse_prefix(value)?;
+        png.trailing_data = value[length..].to_vec();
+
+        Ok(png)
     }
 }
 
@@ -96,7 +230,7 @@ impl std::fmt::Display for Png {
         writeln!(f, "*** BEGIN PNG FILE ***")?;
         writeln!(f, "Number of chunks: {}", self.chunks.len())?;
         for (i, chunk) in self.chunks.iter().enumerate() {
-            writeln!(f, "    Chunk {}: {}", i + 1, chunk.to_string())?;
+            writeln!(f, "    Chunk {}: {}", i + 1, chunk)?;
         }
         writeln!(f, "*** END PNG FILE ***")?;
 
@@ -229,6 +363,39 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "TeSt");
+        assert_eq!(png.chunks().len(), 6);
+    }
+
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = testing_png();
+        let replaced = png
+            .replace_chunk(chunk_from_strings("miDl", "Replaced").unwrap())
+            .unwrap();
+        assert_eq!(&replaced.data_as_string().unwrap(), "I am another chunk");
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(&png.chunks()[2].data_as_string().unwrap(), "Replaced");
+
+        assert!(png
+            .replace_chunk(chunk_from_strings("TeSt", "Message").unwrap())
+            .is_none());
+        assert!(png.chunk_by_type("TeSt").is_none());
+    }
+
+    #[test]
+    fn test_replace_chunk_at() {
+        let mut png = testing_png();
+        let replaced = png.replace_chunk_at(1, chunk_from_strings("TeSt", "Replaced").unwrap());
+        assert_eq!(&replaced.data_as_string().unwrap(), "I am the first chunk");
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(&png.chunks()[1].data_as_string().unwrap(), "Replaced");
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -239,6 +406,125 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        for data in ["first", "second"] {
+            png.append_chunk(chunk_from_strings("TeSt", data).unwrap())
+                .unwrap();
+        }
+
+        let removed = png.remove_chunks(|chunk| chunk.chunk_type().to_string() == "TeSt");
+        let data: Vec<String> = removed
+            .iter()
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+        assert_eq!(data, ["first", "second"]);
+        assert!(png.chunk_by_type("TeSt").is_none());
+        assert_eq!(png.chunks().len(), testing_chunks().len());
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes[..chunk_bytes.len() - 6].iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_error_context() {
+        let chunks = testing_chunks();
+        let offset = 8 + chunks[0].as_bytes().len() + chunks[1].as_bytes().len();
+        let mut bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(Chunk::as_bytes))
+            .collect();
+
+        // Corrupt the last byte of the third chunk's CRC
+        let crc_end = offset + chunks[2].as_bytes().len() - 1;
+        bytes[crc_end] ^= 0xff;
+
+        let error = Png::try_from(bytes.as_ref()).err().unwrap();
+
+        assert!(matches!(
+            &error,
+            Error::InChunk { index: 2, offset: o, source }
+                if *o == offset && matches!(**source, Error::CrcMismatch { .. })
+        ));
+        assert_eq!(error.code(), "crc-mismatch");
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"I am hiding after IEND");
+
+        let mut png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.trailing_data(), b"I am hiding after IEND");
+        assert_eq!(png.as_bytes(), bytes);
+
+        png.remove_trailing_data();
+        assert!(png.trailing_data().is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_image_data() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("IDAT", "first").unwrap());
+        png.insert_chunk(2, chunk_from_strings("IDAT", "second").unwrap());
+        assert_eq!(png.image_data(), b"firstsecond");
+    }
+
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("IDAT", "first").unwrap());
+        png.insert_chunk(2, chunk_from_strings("miDl", "between").unwrap());
+        png.insert_chunk(3, chunk_from_strings("IDAT", "second").unwrap());
+
+        png.replace_image_data(b"replaced", 3);
+
+        let chunk_types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            chunk_types,
+            ["IHDR", "IDAT", "IDAT", "IDAT", "miDl", "FrSt", "miDl", "LASt", "IEND"]
+        );
+        assert_eq!(png.image_data(), b"replaced");
+    }
+
+    #[test]
+    fn test_replace_idat_chunks() {
+        let mut png = testing_png();
+        png.replace_idat_chunks([&b"rep"[..], b"", b"laced"]);
+
+        let lengths: Vec<u32> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+            .map(Chunk::length)
+            .collect();
+        assert_eq!(lengths, [3, 0, 5]);
+        assert_eq!(png.image_data(), b"replaced");
+        assert_eq!(png.chunks()[4].chunk_type().to_string(), "IDAT");
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -246,6 +532,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::iter_cloned_collect)]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
@@ -254,6 +541,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::uninlined_format_args)]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
             .into_iter()
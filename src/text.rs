@@ -0,0 +1,410 @@
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::Error, zlib};
+
+/// Keyword of `iTXt` chunks containing an XMP packet.
+pub(crate) const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// Keyword of the `zTXt` chunk messages are camouflaged as, unless another keyword is given.
+pub(crate) const DEFAULT_KEYWORD: &str = "Comment";
+
+/// Namespace of the Dublin Core properties in XMP packets.
+const DUBLIN_CORE_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+/// Text stored in one of the standard textual chunks: `tEXt`, `zTXt` or `iTXt`.
+///
+/// For more information, check the [PNG Chunk Specifications] page.
+///
+/// [PNG Chunk Specifications]: http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Anc-text
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct TextualData {
+    /// A keyword indicating the type of information represented by the text, such as `Title` or
+    /// `Comment`.
+    pub(crate) keyword: String,
+
+    /// The language the text is written in. Only present in `iTXt` chunks.
+    pub(crate) language_tag: Option<String>,
+
+    /// The keyword translated into the language of the text. Only present in `iTXt` chunks.
+    pub(crate) translated_keyword: Option<String>,
+
+    /// The text itself, decompressed if needed.
+    pub(crate) text: String,
+
+    /// Whether the text is stored compressed in the chunk.
+    pub(crate) compressed: bool,
+}
+
+impl TextualData {
+    /// Creates text to be stored compressed in a `zTXt` chunk.
+    pub(crate) fn compressed(keyword: &str, text: &str) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            language_tag: None,
+            translated_keyword: None,
+            text: text.to_string(),
+            compressed: true,
+        }
+    }
+
+    /// Creates text to be stored uncompressed in an `iTXt` chunk, without a language tag.
+    pub(crate) fn international(keyword: &str, text: &str) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            language_tag: Some(String::new()),
+            translated_keyword: Some(String::new()),
+            text: text.to_string(),
+            compressed: false,
+        }
+    }
+
+    /// Returns the type of chunk the text is stored in.
+    pub(crate) fn chunk_type(&self) -> &'static str {
+        match (&self.language_tag, self.compressed) {
+            (Some(_), _) => "iTXt",
+            (None, true) => "zTXt",
+            (None, false) => "tEXt",
+        }
+    }
+
+    /// Encodes the text into a `tEXt`, `zTXt` or `iTXt` chunk. Fails if the keyword is invalid, or
+    /// the text of a `tEXt` or `zTXt` chunk cannot be represented in ISO 8859-1 (Latin-1).
+    pub(crate) fn to_chunk(&self) -> Result<Chunk, Error> {
+        use std::str::FromStr;
+
+        check_keyword(&self.keyword)?;
+        let keyword = string_to_latin1(&self.keyword)?;
+        let mut data = keyword;
+        data.push(0);
+
+        match (&self.language_tag, &self.translated_keyword) {
+            (Some(language_tag), translated_keyword) => {
+                data.push(u8::from(self.compressed));
+                data.push(0);
+                data.extend_from_slice(language_tag.as_bytes());
+                data.push(0);
+                data.extend_from_slice(
+                    translated_keyword.as_deref().unwrap_or_default().as_bytes(),
+                );
+                data.push(0);
+                if self.compressed {
+                    data.extend(zlib::compress(self.text.as_bytes(), 9));
+                } else {
+                    data.extend_from_slice(self.text.as_bytes());
+                }
+            }
+            (None, _) if self.compressed => {
+                data.push(0);
+                data.extend(zlib::compress(&string_to_latin1(&self.text)?, 9));
+            }
+            (None, _) => data.extend(string_to_latin1(&self.text)?),
+        }
+
+        let chunk_type = ChunkType::from_str(self.chunk_type())?;
+        Ok(Chunk::new(chunk_type, data))
+    }
+
+    /// Decodes the text stored in the chunk. Returns `None` if the chunk is not a textual chunk.
+    pub(crate) fn from_chunk(chunk: &Chunk) -> Option<Result<Self, Error>> {
+        let data = chunk.data();
+        let invalid = || Error::InvalidTextualChunk(chunk.chunk_type().clone());
+
+        let textual_data = match chunk.chunk_type().to_string().as_str() {
+            "tEXt" => split_null(data)
+                .ok_or_else(invalid)
+                .map(|(keyword, text)| Self {
+                    keyword: latin1_to_string(keyword),
+                    language_tag: None,
+                    translated_keyword: None,
+                    text: latin1_to_string(text),
+                    compressed: false,
+                }),
+            "zTXt" => split_null(data)
+                .filter(|(_, rest)| rest.first() == Some(&0))
+                .ok_or_else(invalid)
+                .and_then(|(keyword, rest)| {
+                    Ok(Self {
+                        keyword: latin1_to_string(keyword),
+                        language_tag: None,
+                        translated_keyword: None,
+                        text: latin1_to_string(&zlib::decompress(&rest[1..], zlib::MAX_LENGTH)?),
+                        compressed: true,
+                    })
+                }),
+            "iTXt" => Self::from_international(chunk),
+            _ => return None,
+        };
+
+        Some(textual_data)
+    }
+
+    fn from_international(chunk: &Chunk) -> Result<Self, Error> {
+        let invalid = || Error::InvalidTextualChunk(chunk.chunk_type().clone());
+
+        let (keyword, rest) = split_null(chunk.data()).ok_or_else(invalid)?;
+        let (&compressed, rest) = rest.split_first().ok_or_else(invalid)?;
+        let (&_compression_method, rest) = rest.split_first().ok_or_else(invalid)?;
+        let (language_tag, rest) = split_null(rest).ok_or_else(invalid)?;
+        let (translated_keyword, text) = split_null(rest).ok_or_else(invalid)?;
+
+        let text = if compressed == 1 {
+            zlib::decompress(text, zlib::MAX_LENGTH)?
+        } else {
+            text.to_vec()
+        };
+
+        Ok(Self {
+            keyword: latin1_to_string(keyword),
+            language_tag: Some(String::from_utf8_lossy(language_tag).into_owned()),
+            translated_keyword: Some(String::from_utf8_lossy(translated_keyword).into_owned()),
+            text: String::from_utf8(text)?,
+            compressed: compressed == 1,
+        })
+    }
+}
+
+/// Splits the data at the first null separator, excluding the separator.
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let position = data.iter().position(|&byte| byte == 0)?;
+    Some((&data[..position], &data[position + 1..]))
+}
+
+/// Checks that a keyword can be stored in a textual chunk: it must be 1 to 79 characters long, and
+/// consist of ISO 8859-1 (Latin-1) characters other than null.
+pub(crate) fn check_keyword(keyword: &str) -> Result<(), Error> {
+    if keyword.is_empty() || keyword.chars().count() > 79 || keyword.contains('\0') {
+        return Err(Error::InvalidKeyword(keyword.to_string()));
+    }
+    string_to_latin1(keyword).map(|_| ())
+}
+
+/// Converts a string to ISO 8859-1 (Latin-1) encoded bytes, as used by `tEXt` and `zTXt` chunks.
+fn string_to_latin1(s: &str) -> Result<Vec<u8>, Error> {
+    s.chars()
+        .map(|c| u8::try_from(c).map_err(|_| Error::NonLatin1Text(s.to_string())))
+        .collect()
+}
+
+/// Wraps `description` in a minimal XMP packet, as the Dublin Core `description` property.
+pub(crate) fn xmp_packet(description: &str) -> String {
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\" xmlns:dc=\"{DUBLIN_CORE_NAMESPACE}\">
+{}  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>",
+        xmp_description_property(description)
+    )
+}
+
+/// Adds `description` to an existing XMP packet as the Dublin Core `description` property, in its
+/// first `rdf:Description` element. Fails if the packet already has a description, so that
+/// existing metadata is never overwritten.
+pub(crate) fn xmp_with_description(packet: &str, description: &str) -> Result<String, Error> {
+    let invalid = |message: &str| Error::InvalidXmpPacket(message.to_string());
+
+    if packet.contains("dc:description") {
+        return Err(Error::XmpDescriptionExists);
+    }
+
+    let start = packet
+        .find("<rdf:Description")
+        .ok_or_else(|| invalid("the rdf:Description element is missing"))?;
+    let end = packet[start..]
+        .find('>')
+        .map(|end| start + end)
+        .ok_or_else(|| invalid("the rdf:Description element is truncated"))?;
+
+    let (tag, closing_tag) = match packet[start..end].strip_suffix('/') {
+        Some(tag) => (tag.trim_end(), "  </rdf:Description>"),
+        None => (&packet[start..end], ""),
+    };
+    let namespace = if packet[..end].contains("xmlns:dc=") {
+        String::new()
+    } else {
+        format!(" xmlns:dc=\"{DUBLIN_CORE_NAMESPACE}\"")
+    };
+
+    Ok(format!(
+        "{}{tag}{namespace}>\n{}{closing_tag}{}",
+        &packet[..start],
+        xmp_description_property(description),
+        &packet[end + 1..]
+    ))
+}
+
+/// Returns the Dublin Core `description` property of an XMP packet, with `description` as its
+/// default value.
+fn xmp_description_property(description: &str) -> String {
+    let description = description
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+
+    format!(
+        "   <dc:description>
+    <rdf:Alt>
+     <rdf:li xml:lang=\"x-default\">{description}</rdf:li>
+    </rdf:Alt>
+   </dc:description>
+"
+    )
+}
+
+/// Returns the Dublin Core `description` property of an XMP packet, if present.
+pub(crate) fn xmp_description(packet: &str) -> Option<String> {
+    let (_, description) = packet.split_once("<dc:description>")?;
+    let (description, _) = description.split_once("</dc:description>")?;
+    let (_, description) = description.split_once("<rdf:li")?;
+    let (_, description) = description.split_once('>')?;
+    let (description, _) = description.split_once("</rdf:li>")?;
+
+    Some(
+        description
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// Converts ISO 8859-1 (Latin-1) encoded bytes, as used by `tEXt` and `zTXt` chunks, to a string.
+fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk_from_bytes(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_text_chunk() {
+        let chunk = chunk_from_bytes("tEXt", b"Comment\0Caf\xe9");
+        let textual_data = TextualData::from_chunk(&chunk).unwrap().unwrap();
+        assert_eq!(textual_data.keyword, "Comment");
+        assert_eq!(textual_data.text, "Café");
+        assert!(!textual_data.compressed);
+    }
+
+    #[test]
+    fn test_compressed_text_chunk() {
+        let mut data = b"Software\0\0".to_vec();
+        data.extend(zlib::compress(b"pngme", 9));
+
+        let chunk = chunk_from_bytes("zTXt", &data);
+        let textual_data = TextualData::from_chunk(&chunk).unwrap().unwrap();
+        assert_eq!(textual_data.keyword, "Software");
+        assert_eq!(textual_data.text, "pngme");
+        assert!(textual_data.compressed);
+    }
+
+    #[test]
+    fn test_international_text_chunk() {
+        let mut data = b"Title\0\x01\0fr\0Titre\0".to_vec();
+        data.extend(zlib::compress("Café".as_bytes(), 9));
+
+        let chunk = chunk_from_bytes("iTXt", &data);
+        let textual_data = TextualData::from_chunk(&chunk).unwrap().unwrap();
+        assert_eq!(textual_data.keyword, "Title");
+        assert_eq!(textual_data.language_tag.as_deref(), Some("fr"));
+        assert_eq!(textual_data.translated_keyword.as_deref(), Some("Titre"));
+        assert_eq!(textual_data.text, "Café");
+        assert!(textual_data.compressed);
+    }
+
+    #[test]
+    fn test_invalid_textual_chunk() {
+        let chunk = chunk_from_bytes("zTXt", b"Comment");
+        assert!(TextualData::from_chunk(&chunk).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_textual_data_to_chunk() {
+        let textual_data = TextualData::compressed("Comment", "Café");
+        let chunk = textual_data.to_chunk().unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "zTXt");
+        assert_eq!(
+            TextualData::from_chunk(&chunk).unwrap().unwrap(),
+            textual_data
+        );
+
+        let textual_data = TextualData::international("Title", "Ünïcödé ✓");
+        let chunk = textual_data.to_chunk().unwrap();
+        assert_eq!(&chunk.chunk_type().to_string(), "iTXt");
+        assert_eq!(
+            TextualData::from_chunk(&chunk).unwrap().unwrap(),
+            textual_data
+        );
+
+        assert!(TextualData::compressed("Comment", "✓").to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_xmp_packet() {
+        let packet = xmp_packet("Fish & <chips>");
+        assert!(packet.contains("Fish &amp; &lt;chips&gt;"));
+        assert_eq!(xmp_description(&packet).as_deref(), Some("Fish & <chips>"));
+        assert!(xmp_description("<x:xmpmeta/>").is_none());
+    }
+
+    #[test]
+    fn test_xmp_with_description() {
+        let packet = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">
+   <xmp:CreatorTool>pngme</xmp:CreatorTool>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>";
+        let updated = xmp_with_description(packet, "Fish & <chips>").unwrap();
+        assert!(updated.contains("<xmp:CreatorTool>pngme</xmp:CreatorTool>"));
+        assert!(updated.contains(&format!("xmlns:dc=\"{DUBLIN_CORE_NAMESPACE}\"")));
+        assert_eq!(xmp_description(&updated).as_deref(), Some("Fish & <chips>"));
+
+        let self_closing = "<rdf:RDF><rdf:Description rdf:about=\"\"/></rdf:RDF>";
+        let updated = xmp_with_description(self_closing, "message").unwrap();
+        assert!(updated.ends_with("</rdf:Description></rdf:RDF>"));
+        assert_eq!(xmp_description(&updated).as_deref(), Some("message"));
+
+        assert!(matches!(
+            xmp_with_description(&xmp_packet("existing"), "message"),
+            Err(Error::XmpDescriptionExists)
+        ));
+        assert!(matches!(
+            xmp_with_description("<x:xmpmeta/>", "message"),
+            Err(Error::InvalidXmpPacket(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_keyword() {
+        assert!(check_keyword("Comment").is_ok());
+        assert!(check_keyword("Café").is_ok());
+        assert!(check_keyword(&"k".repeat(79)).is_ok());
+
+        assert!(matches!(check_keyword(""), Err(Error::InvalidKeyword(_))));
+        assert!(matches!(
+            check_keyword(&"k".repeat(80)),
+            Err(Error::InvalidKeyword(_))
+        ));
+        assert!(matches!(
+            check_keyword("Key\0word"),
+            Err(Error::InvalidKeyword(_))
+        ));
+        assert!(matches!(check_keyword("✓"), Err(Error::NonLatin1Text(_))));
+    }
+
+    #[test]
+    fn test_non_textual_chunk() {
+        let chunk = chunk_from_bytes("ruSt", b"Comment\0Text");
+        assert!(TextualData::from_chunk(&chunk).is_none());
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{Read, Write};
+
+use crate::error::Error;
+
+/// Largest amount of data to decompress when its length isn't known in advance, so that a small
+/// malicious stream can't exhaust memory.
+pub(crate) const MAX_LENGTH: usize = 1 << 28;
+
+/// Compresses `data` into a zlib stream with the given compression level (0-9).
+pub(crate) fn compress(data: &[u8], level: u32) -> Vec<u8> {
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+    encoder
+        .write_all(data)
+        .expect("Writing to a Vec should not fail");
+    encoder.finish().expect("Writing to a Vec should not fail")
+}
+
+/// Decompresses a zlib stream. Fails if the stream is malformed, incomplete, or its checksum
+/// doesn't match, or if it decompresses to more than `limit` bytes.
+pub(crate) fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    use flate2::read::ZlibDecoder;
+
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(Error::DecompressionFailed)?;
+
+    if decompressed.len() > limit {
+        return Err(Error::DecompressionFailed(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("the data is longer than {limit} bytes"),
+        )));
+    }
+
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = b"This is where your secret message will be!".repeat(10);
+        let compressed = compress(&data, 9);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_decompress_invalid_data() {
+        assert!(decompress(b"This is not a zlib stream", MAX_LENGTH).is_err());
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let compressed = compress(&vec![0; 1 << 20], 9);
+        assert!(decompress(&compressed, 1 << 20).is_ok());
+        assert!(matches!(
+            decompress(&compressed, (1 << 20) - 1),
+            Err(Error::DecompressionFailed(_))
+        ));
+    }
+}